@@ -1,4 +1,4 @@
-import { LeaderboardState, Chat } from "./types/TerryLeaderboard";
+import { LeaderboardState, Chat, ConversationId, ConversationSummary, SearchHit, Attachment, Revelation, ChatFilter, FilterKind, FilterAction, ModerationView, LeaderboardPage, LeaderboardWindow, Offering, RemoveMode, RespectRules, RespectRulesView, RankedCongregation, RankHistory } from "./types/TerryLeaderboard";
 
 const BASE_URL = import.meta.env.BASE_URL;
 
@@ -13,9 +13,99 @@ export const getLeaderboard = async (): Promise<LeaderboardState | null> => {
     }
 };
 
-export const getChat = async (): Promise<Chat | null> => {
+export interface LeaderboardQuery {
+    window?: LeaderboardWindow;
+    sort?: "respects" | "score" | string; // any other value is an offering id, score and offerings need window all_time
+    ranking?: "competition" | "dense";
+    scope?: "contacts" | "extended";
+    page?: number; // 1-based
+    per_page?: number;
+}
+
+export const getRankedLeaderboard = async (query: LeaderboardQuery = {}): Promise<LeaderboardPage | null> => {
+    try {
+        const params = new URLSearchParams(
+            Object.entries(query).filter(([, value]) => value !== undefined).map(([key, value]) => [key, String(value)])
+        );
+        const response = await fetch(`${BASE_URL}/leaderboard?${params}`);
+        if (!response.ok) throw new Error("Failed to fetch ranked leaderboard");
+        return response.json();
+    } catch (error) {
+        console.error(error);
+        return null;
+    }
+};
+
+export const getRankHistory = async (): Promise<RankHistory | null> => {
     try {
-        const response = await fetch(`${BASE_URL}/get_chat`);
+        const response = await fetch(`${BASE_URL}/rank_history`);
+        if (!response.ok) return null;
+        return await response.json();
+    } catch (error) {
+        console.error(error);
+        return null;
+    }
+}
+
+// same query as the leaderboard, minus paging
+export const getCongregations = async (query: LeaderboardQuery = {}): Promise<RankedCongregation[] | null> => {
+    try {
+        const params = new URLSearchParams(
+            Object.entries(query).filter(([, value]) => value !== undefined).map(([key, value]) => [key, String(value)])
+        );
+        const response = await fetch(`${BASE_URL}/congregations?${params}`);
+        if (!response.ok) throw new Error("Failed to fetch congregations");
+        return response.json();
+    } catch (error) {
+        console.error(error);
+        return null;
+    }
+};
+
+const postCongregation = async (body: object): Promise<Response | null> => {
+    try {
+        return await fetch(`${BASE_URL}/congregations`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify(body),
+        });
+    } catch (error) {
+        console.error(error);
+        return null;
+    }
+}
+
+export const createCongregation = async (name: string): Promise<string | null> => {
+    const response = await postCongregation({ Create: { name } });
+    return response?.ok ? (await response.json()).congregation_id : null;
+}
+export const inviteToCongregation = async (congregationId: string, node: string) =>
+    (await postCongregation({ Invite: { congregation_id: congregationId, node } }))?.ok ?? false;
+export const acceptCongregationInvite = async (congregationId: string) =>
+    (await postCongregation({ Accept: { congregation_id: congregationId } }))?.ok ?? false;
+export const declineCongregationInvite = async (congregationId: string) =>
+    (await postCongregation({ Decline: { congregation_id: congregationId } }))?.ok ?? false;
+export const leaveCongregation = async (congregationId: string) =>
+    (await postCongregation({ Leave: { congregation_id: congregationId } }))?.ok ?? false;
+export const removeFromCongregation = async (congregationId: string, node: string) =>
+    (await postCongregation({ Remove: { congregation_id: congregationId, node } }))?.ok ?? false;
+
+export interface ChatQuery {
+    conversation?: string; // "shrine", "direct:<node>" or "room:<room_id>"
+    sender?: string;
+    since?: number; // unix millis
+    until?: number;
+    before?: string; // next_cursor from a previous page
+    limit?: number;
+    include_hidden?: boolean;
+}
+
+export const getChat = async (query: ChatQuery = {}): Promise<Chat | null> => {
+    try {
+        const params = new URLSearchParams(
+            Object.entries(query).filter(([, value]) => value !== undefined).map(([key, value]) => [key, String(value)])
+        );
+        const response = await fetch(`${BASE_URL}/get_chat?${params}`);
         if (!response.ok) throw new Error("Failed to fetch chat");
         return response.json();
     } catch (error) {
@@ -24,12 +114,127 @@ export const getChat = async (): Promise<Chat | null> => {
     }
 };
 
-export const addRespect = async (nodeId: string): Promise<boolean> => {
+export const searchChat = async (q: string, conversation?: string, limit?: number): Promise<SearchHit[] | null> => {
+    try {
+        const params = new URLSearchParams({ q });
+        if (conversation) params.set("conversation", conversation);
+        if (limit) params.set("limit", String(limit));
+        const response = await fetch(`${BASE_URL}/search_chat?${params}`);
+        if (!response.ok) throw new Error("Failed to search chat");
+        const { results } = await response.json();
+        return results;
+    } catch (error) {
+        console.error(error); 
+        return null;
+    }
+};
+
+export const godSays = async (count: number = 1, daily: boolean = false): Promise<Revelation | null> => {
+    try {
+        const params = new URLSearchParams({ count: String(count), daily: String(daily) });
+        const response = await fetch(`${BASE_URL}/god_says?${params}`);
+        if (!response.ok) return null;
+        return await response.json();
+    } catch (error) {
+        console.error(error);
+        return null;
+    }
+}
+
+export const getModeration = async (): Promise<ModerationView | null> => {
+    try {
+        const response = await fetch(`${BASE_URL}/moderation`);
+        if (!response.ok) return null;
+        return await response.json();
+    } catch (error) {
+        console.error(error);
+        return null;
+    }
+}
+
+const postModeration = async (body: object): Promise<Response | null> => {
+    try {
+        return await fetch(`${BASE_URL}/moderation`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify(body),
+        });
+    } catch (error) {
+        console.error(error);
+        return null;
+    }
+}
+
+export const muteNode = async (node: string) => (await postModeration({ Mute: { node } }))?.ok ?? false;
+export const unmuteNode = async (node: string) => (await postModeration({ Unmute: { node } }))?.ok ?? false;
+export const removeChatFilter = async (id: string) => (await postModeration({ RemoveFilter: { id } }))?.ok ?? false;
+export const addChatFilter = async (pattern: string, kind: FilterKind, action: FilterAction): Promise<ChatFilter | null> => {
+    const response = await postModeration({ AddFilter: { pattern, kind, action } });
+    return response?.ok ? response.json() : null;
+}
+
+export const getOfferings = async (): Promise<Record<string, Offering> | null> => {
+    try {
+        const response = await fetch(`${BASE_URL}/offerings`);
+        if (!response.ok) return null;
+        return await response.json();
+    } catch (error) {
+        console.error(error);
+        return null;
+    }
+}
+
+const postOfferings = async (body: object): Promise<Response | null> => {
+    try {
+        return await fetch(`${BASE_URL}/offerings`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify(body),
+        });
+    } catch (error) {
+        console.error(error);
+        return null;
+    }
+}
+
+export const setOffering = async (id: string, name: string, weight: number): Promise<Offering | null> => {
+    const response = await postOfferings({ SetOffering: { id, name, weight } });
+    return response?.ok ? response.json() : null;
+}
+export const removeOffering = async (id: string) => (await postOfferings({ RemoveOffering: { id } }))?.ok ?? false;
+
+export const getRespectRules = async (): Promise<RespectRulesView | null> => {
+    try {
+        const response = await fetch(`${BASE_URL}/respect_rules`);
+        if (!response.ok) return null;
+        return await response.json();
+    } catch (error) {
+        console.error(error);
+        return null;
+    }
+}
+
+// fields left out go back to their defaults
+export const setRespectRules = async (rules: Partial<RespectRules>): Promise<RespectRulesView | null> => {
+    try {
+        const response = await fetch(`${BASE_URL}/respect_rules`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify(rules),
+        });
+        return response.ok ? response.json() : null;
+    } catch (error) {
+        console.error(error);
+        return null;
+    }
+}
+
+export const addRespect = async (nodeId: string, offering?: string): Promise<boolean> => {
     try {
         const response = await fetch(`${BASE_URL}/add_respect`, {
             method: "POST",
             headers: { "Content-Type": "application/json"},
-            body: JSON.stringify( {node: nodeId} ),
+            body: JSON.stringify( {node: nodeId, offering} ),
         });
 
         return response.ok;
@@ -98,12 +303,147 @@ export const declineContactRequest = async (nodeId: string): Promise<boolean> =>
     }
 }
 
-export const sendChatMessage = async (content: string): Promise<boolean> => {
+export const sendChatMessage = async (content: string, conversation: ConversationId = "Shrine", attachments: string[] = []): Promise<boolean> => {
     try {
         const response = await fetch(`${BASE_URL}/send_chat_message`, {
             method: "POST",
             headers: { "Content-Type": "application/json"},
-            body: JSON.stringify( {content} ),
+            body: JSON.stringify( {content, conversation, attachments} ),
+        });
+
+        return response.ok;
+    } catch (error) {
+        console.error(error); 
+        return false;
+    }
+}
+
+export const uploadAttachment = async (file: File): Promise<Attachment | null> => {
+    try {
+        const params = new URLSearchParams({ name: file.name, mime: file.type });
+        const response = await fetch(`${BASE_URL}/upload_attachment?${params}`, {
+            method: "POST",
+            body: file,
+        });
+        if (!response.ok) return null;
+        return await response.json();
+    } catch (error) {
+        console.error(error);
+        return null;
+    }
+}
+
+export const attachmentUrl = (attachment: Attachment): string =>
+    `${BASE_URL}/attachment?${new URLSearchParams({ owner: attachment.owner, id: attachment.id })}`;
+
+export const sendTyping = async (conversation: ConversationId = "Shrine"): Promise<boolean> => {
+    try {
+        const response = await fetch(`${BASE_URL}/typing`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json"},
+            body: JSON.stringify( {conversation} ),
+        });
+
+        return response.ok;
+    } catch (error) {
+        console.error(error); 
+        return false;
+    }
+}
+
+export const editChatMessage = async (id: string, content: string): Promise<boolean> => {
+    try {
+        const response = await fetch(`${BASE_URL}/edit_chat_message`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json"},
+            body: JSON.stringify( {id, content} ),
+        });
+
+        return response.ok;
+    } catch (error) {
+        console.error(error); 
+        return false;
+    }
+}
+
+export const deleteChatMessage = async (id: string): Promise<boolean> => {
+    try {
+        const response = await fetch(`${BASE_URL}/delete_chat_message`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json"},
+            body: JSON.stringify( {id} ),
+        });
+
+        return response.ok;
+    } catch (error) {
+        console.error(error); 
+        return false;
+    }
+}
+
+export const react = async (id: string, emoji: string, remove: boolean = false): Promise<boolean> => {
+    try {
+        const response = await fetch(`${BASE_URL}/react`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json"},
+            body: JSON.stringify( {id, emoji, remove} ),
+        });
+
+        return response.ok;
+    } catch (error) {
+        console.error(error); 
+        return false;
+    }
+}
+
+export const getConversations = async (): Promise<ConversationSummary[] | null> => {
+    try {
+        const response = await fetch(`${BASE_URL}/get_conversations`);
+        if (!response.ok) throw new Error("Failed to fetch conversations");
+        return response.json();
+    } catch (error) {
+        console.error(error); 
+        return null;
+    }
+};
+
+export const markRead = async (conversation: ConversationId = "Shrine"): Promise<boolean> => {
+    try {
+        const response = await fetch(`${BASE_URL}/mark_read`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json"},
+            body: JSON.stringify( {conversation} ),
+        });
+
+        return response.ok;
+    } catch (error) {
+        console.error(error); 
+        return false;
+    }
+}
+
+export const createRoom = async (name: string): Promise<string | null> => {
+    try {
+        const response = await fetch(`${BASE_URL}/create_room`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json"},
+            body: JSON.stringify( {name} ),
+        });
+        if (!response.ok) throw new Error("Failed to create room");
+        const { room_id } = await response.json();
+        return room_id;
+    } catch (error) {
+        console.error(error); 
+        return null;
+    }
+}
+
+const postRoomAction = async (path: string, body: { room_id: string, node?: string }): Promise<boolean> => {
+    try {
+        const response = await fetch(`${BASE_URL}/${path}`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json"},
+            body: JSON.stringify(body),
         });
 
         return response.ok;
@@ -113,12 +453,18 @@ export const sendChatMessage = async (content: string): Promise<boolean> => {
     }
 }
 
-export const removeLeaderboardEntry = async (nodeId: string): Promise<boolean> => { 
+export const inviteToRoom = (roomId: string, nodeId: string) => postRoomAction("invite_to_room", { room_id: roomId, node: nodeId });
+export const acceptRoomInvite = (roomId: string) => postRoomAction("accept_room_invite", { room_id: roomId });
+export const declineRoomInvite = (roomId: string) => postRoomAction("decline_room_invite", { room_id: roomId });
+export const leaveRoom = (roomId: string) => postRoomAction("leave_room", { room_id: roomId });
+export const kickFromRoom = (roomId: string, nodeId: string) => postRoomAction("kick_from_room", { room_id: roomId, node: nodeId });
+
+export const removeLeaderboardEntry = async (nodeId: string, mode: RemoveMode = "Hide"): Promise<boolean> => { 
     try {
         const response = await fetch(`${BASE_URL}/remove_leaderboard_entry`, {
             method: "POST",
             headers: { "Content-Type": "application/json"},
-            body: JSON.stringify( {node: nodeId} ),
+            body: JSON.stringify( {node: nodeId, mode} ),
         });
 
         return response.ok;
@@ -30,6 +30,10 @@ const useShrineStore = create<ShrineStore>()(
                 pending_contact_requests: [],
                 incoming_contact_requests: [],
                 chat_history: [],
+                chat_unread: 0,
+                direct_conversations: {},
+                rooms: {},
+                room_invites: {},
             }, 
             chat: {
                 chat_history: []
@@ -90,6 +94,7 @@ const useShrineStore = create<ShrineStore>()(
                 const success = await sendChatMessage(content);
                 if (success) {
                     const newChatMessage: ChatMessage = { 
+                        id: `${get().leaderboard.node_id}:local:${Date.now()}`,
                         sender: get().leaderboard.node_id,
                         content,
                         timestamp: Date.now(),
@@ -5,6 +5,61 @@ export interface Address {
 
 export interface LeaderboardEntry {
     respects: number;
+    windows?: WindowCounts;
+    streak?: Streak;
+    achievements?: Achievement[];
+    offerings?: Record<string, number>;
+    score?: number;
+    diminished?: Record<string, number>;
+}
+
+export interface RespectRules {
+    cooldown_secs: number;
+    daily_cap: number | null;
+    diminishing_secs: number | null;
+}
+
+// /add_respect answers with this too, alongside an error when it's a 429
+export interface RespectAllowance {
+    remaining_today: number | null;
+    retry_after_secs: number;
+    next_credit_percent: number;
+}
+
+export interface RespectRulesView {
+    rules: RespectRules;
+    allowance: RespectAllowance;
+}
+
+export interface Offering {
+    id: string;
+    name: string;
+    weight: number;
+}
+
+export interface Streak {
+    current: number;
+    longest: number;
+    last_day: number | null;
+}
+
+export type AchievementKind = "FirstRespect" | "WeekStreak" | "Respects100" | "Respects777";
+
+export interface Achievement {
+    kind: AchievementKind;
+    earned_at: unknown;
+}
+
+export type LeaderboardWindow = "daily" | "weekly" | "monthly" | "all_time";
+
+// counts for the utc day/week/month ids next to them, stale ids mean the count has rolled over
+export interface WindowCounts {
+    day: number;
+    daily: number;
+    week: number;
+    weekly: number;
+    month: number;
+    monthly: number;
 }
 
  export interface LeaderboardState {
@@ -15,14 +70,212 @@ export interface LeaderboardEntry {
     pending_contact_requests: string[];
     incoming_contact_requests: string[];
     chat_history: ChatMessage[];
+    chat_unread: number;
+    direct_conversations: Record<string, Conversation>;
+    rooms: Record<string, Room>;
+    room_invites: Record<string, RoomInvite>;
+    congregations?: Record<string, Congregation>;
+    congregation_invites?: Record<string, CongregationInvite>;
+    mutes?: string[];
+    chat_filters?: ChatFilter[];
+    offering_catalogue?: Record<string, Offering>;
+    respect_rules?: RespectRules;
+    hidden_entries?: string[];
+    deleted_entries?: string[];
  }
 
+// Hide keeps the entry updating off the boards, Delete drops it for good, Restore undoes either
+export type RemoveMode = "Hide" | "Delete" | "Restore";
+
+export interface RankedEntry {
+    node: string;
+    respects: number;
+    score: number;
+    offerings: Record<string, number>;
+    value: number; // whatever the board is sorted by
+    rank: number;
+    tied: boolean;
+    is_us: boolean;
+    value_delta: number | null;
+    rank_delta: number | null;
+    hops: number;
+    path: string[];
+    updated_at: unknown | null;
+    streak: number;
+    achievements: AchievementKind[];
+}
+
+// moves on the main board: contacts, all-time respects, competition ranking
+export interface RankChange {
+    node: string;
+    from: number | null; // null when it just appeared
+    to: number | null; // null when it left the board
+    at: unknown;
+}
+
+export type RankNotificationKind =
+    | { OvertookUs: { node: string } }
+    | { WeOvertook: { node: string } }
+    | "ReachedFirst"
+    | { LostFirst: { to: string } };
+
+export interface RankNotification {
+    kind: RankNotificationKind;
+    rank: number; // ours, after the change
+    at: unknown;
+}
+
+export interface RankHistory {
+    standings: Record<string, number>;
+    changes: RankChange[];
+    notifications: RankNotification[];
+}
+
+// the founder decides membership, but only after the invitee accepts
+export interface Congregation {
+    name: string;
+    founder: string;
+    members: string[];
+    invited: string[];
+    entries: Record<string, LeaderboardEntry>;
+}
+
+export interface CongregationInvite {
+    name: string;
+    founder: string;
+    members: string[];
+}
+
+export interface RankedCongregation {
+    congregation_id: string;
+    name: string;
+    founder: string;
+    members: string[];
+    total: number;
+    rank: number;
+    tied: boolean;
+    includes_us: boolean;
+    missing: string[];
+}
+
+export interface LeaderboardPage {
+    entries: RankedEntry[];
+    ours: RankedEntry | null;
+    total: number;
+    page: number;
+    per_page: number;
+    pages: number;
+    congregations: RankedCongregation[];
+}
+
+export type DeliveryStatus = "Queued" | "Sent" | "Delivered" | "Read" | "Rejected";
+
 export interface ChatMessage {
+    id: string;
     sender: string;
     content: string;
     timestamp: number;
+    receipts?: Record<string, DeliveryStatus>;
+    conversation?: ConversationId;
+    edited_at?: unknown | null;
+    edit_history?: string[];
+    deleted?: boolean;
+    reactions?: Record<string, string[]>;
+    mentions?: string[];
+    attachments?: Attachment[];
+    moderation?: Moderation | null;
+}
+
+export type FilterKind = "Keyword" | "Regex";
+export type FilterAction = "Collapse" | "Hide";
+
+export interface ChatFilter {
+    id: string;
+    pattern: string;
+    kind: FilterKind;
+    action: FilterAction;
+}
+
+export interface Moderation {
+    action: FilterAction;
+    reason: "Muted" | { Filter: string };
+}
+
+export interface ModerationView {
+    mutes: string[];
+    filters: ChatFilter[];
+    filtered: ChatMessage[];
+}
+
+export interface Revelation {
+    words: string[];
+    day: number | null;
+}
+
+export interface Attachment {
+    id: string;
+    owner: string;
+    name: string;
+    mime: string;
+    size: number;
+}
+
+export type ConversationId = "Shrine" | { Direct: string } | { Room: string };
+
+export interface Room {
+    name: string;
+    owner: string;
+    members: string[];
+    invited: string[];
+    history: ChatMessage[];
+    unread: number;
+}
+
+export interface RoomInvite {
+    name: string;
+    owner: string;
+    members: string[];
+}
+
+export type RoomEvent = { Joined: string } | { Left: string } | { Kicked: string };
+
+export interface Conversation {
+    history: ChatMessage[];
+    unread: number;
+}
+
+export interface ConversationSummary {
+    conversation: ConversationId;
+    unread: number;
+    unread_mentions: number;
+    last_message: ChatMessage | null;
+}
+
+export interface SearchHit {
+    message: ChatMessage;
+    score: number;
+    snippet: string;
+    highlights: [number, number][]; // [start, end) char offsets into snippet
 }
 
 export interface Chat {
     chat_history: ChatMessage[]
+    next_cursor?: string | null
 }
+
+export type WsUpdate =
+    | { Typing: { node: string; conversation: ConversationId; expires_in_ms: number } }
+    | { Receipt: { id: string; recipient: string; status: DeliveryStatus } }
+    | { RoomInvite: { room_id: string; name: string; owner: string } }
+    | { RoomEvent: { room_id: string; event: RoomEvent } }
+    | { RoomClosed: { room_id: string } }
+    | { MessageEdited: { id: string; content: string; edited_at: unknown } }
+    | { MessageDeleted: { id: string } }
+    | { AttachmentFetched: { owner: string; id: string; found: boolean } }
+    | { Reaction: { id: string; emoji: string; node: string; added: boolean } }
+    | { HistorySynced: { node: string; added: number } }
+    | { Mention: { id: string; sender: string; conversation: ConversationId; content: string } }
+    | { CongregationInvite: { congregation_id: string; name: string; founder: string } }
+    | { CongregationMembership: { congregation_id: string; members: string[] } }
+    | { CongregationGone: { congregation_id: string } }
+    | { RankNotification: { notification: RankNotification; message: string } };
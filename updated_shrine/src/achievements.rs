@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+use crate::structs::{LeaderboardEntry, State};
+use crate::windows::Window;
+
+/// consecutive utc days with at least one respect
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Streak {
+    pub current: u32,
+    pub longest: u32,
+    pub last_day: Option<u64>, // Window::Daily period
+}
+
+impl Streak {
+    /// the streak as of `now`. one that missed yesterday is already broken, even before the node says so
+    pub fn current_at(&self, now: SystemTime) -> u32 {
+        match self.last_day {
+            Some(last_day) if last_day + 1 >= Window::Daily.period(now) => self.current,
+            _ => 0,
+        }
+    }
+
+    fn record(&mut self, day: u64) {
+        match self.last_day {
+            Some(last_day) if last_day == day => return,
+            Some(last_day) if last_day + 1 == day => self.current += 1,
+            _ => self.current = 1,
+        }
+        self.last_day = Some(day);
+        self.longest = self.longest.max(self.current);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AchievementKind {
+    FirstRespect,
+    WeekStreak,
+    Respects100,
+    Respects777,
+}
+
+impl AchievementKind {
+    pub fn title(&self) -> &'static str {
+        match self {
+            AchievementKind::FirstRespect => "First Respect",
+            AchievementKind::WeekStreak => "Seven Days Devoted",
+            AchievementKind::Respects100 => "A Hundred Respects",
+            AchievementKind::Respects777 => "Seven Hundred Seventy-Seven",
+        }
+    }
+
+    fn earned_by(&self, entry: &LeaderboardEntry) -> bool {
+        match self {
+            AchievementKind::FirstRespect => entry.respects >= 1,
+            AchievementKind::WeekStreak => entry.streak.current >= 7,
+            AchievementKind::Respects100 => entry.respects >= 100,
+            AchievementKind::Respects777 => entry.respects >= 777,
+        }
+    }
+}
+
+const ALL_ACHIEVEMENTS: [AchievementKind; 4] = [
+    AchievementKind::FirstRespect,
+    AchievementKind::WeekStreak,
+    AchievementKind::Respects100,
+    AchievementKind::Respects777,
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Achievement {
+    pub kind: AchievementKind,
+    pub earned_at: SystemTime,
+}
+
+impl State {
+    /// moves our streak along for a respect paid at `at` and returns whatever it newly earned us
+    pub fn record_devotion(&mut self, at: SystemTime) -> Vec<AchievementKind> {
+        let entry = self.stats.entry(self.node_id.clone()).or_default();
+        entry.streak.record(Window::Daily.period(at));
+        let earned: Vec<AchievementKind> = ALL_ACHIEVEMENTS.into_iter()
+            .filter(|kind| kind.earned_by(entry) && !entry.achievements.iter().any(|achievement| achievement.kind == *kind))
+            .collect();
+        for kind in &earned {
+            entry.achievements.push(Achievement { kind: *kind, earned_at: at });
+        }
+        earned
+    }
+}
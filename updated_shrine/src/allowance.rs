@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+use crate::structs::State;
+use crate::windows::{Window, SECS_PER_DAY};
+
+const MAX_RULE_SECS: u64 = SECS_PER_DAY;
+/// after this many rapid repeats a respect is worth nothing
+const MAX_DIMINISHING_STEPS: usize = 7;
+
+/// how often we may pay respects. set through POST /respect_rules, missing fields take the defaults
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RespectRules {
+    pub cooldown_secs: u64, // 0 turns it off
+    pub daily_cap: Option<u64>, // utc day, None is unlimited
+    /// a respect paid within this many seconds of the previous one scores half of what that one did
+    pub diminishing_secs: Option<u64>,
+}
+
+impl Default for RespectRules {
+    fn default() -> Self {
+        RespectRules { cooldown_secs: 2, daily_cap: Some(100), diminishing_secs: None }
+    }
+}
+
+impl RespectRules {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.cooldown_secs > MAX_RULE_SECS {
+            return Err(format!("cooldown_secs goes up to {}", MAX_RULE_SECS));
+        }
+        if self.daily_cap == Some(0) {
+            return Err("daily_cap has to allow at least one respect, use null for no cap".to_string());
+        }
+        if matches!(self.diminishing_secs, Some(secs) if secs == 0 || secs > MAX_RULE_SECS) {
+            return Err(format!("diminishing_secs is 1 to {}, or null to turn it off", MAX_RULE_SECS));
+        }
+        Ok(())
+    }
+}
+
+/// what's left under our rules right now
+#[derive(Debug, Clone, Serialize)]
+pub struct RespectAllowance {
+    pub remaining_today: Option<u64>, // None without a daily cap
+    pub retry_after_secs: u64, // 0 when a respect can be paid now
+    /// share of the offering's weight the next respect scores, 100 unless it's a rapid repeat
+    pub next_credit_percent: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RespectRefused {
+    UnknownOffering(String),
+    Cooldown,
+    DailyCap,
+}
+
+impl std::fmt::Display for RespectRefused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RespectRefused::UnknownOffering(offering) => write!(f, "no offering {:?}", offering),
+            RespectRefused::Cooldown => write!(f, "still cooling down from the last respect"),
+            RespectRefused::DailyCap => write!(f, "daily cap reached"),
+        }
+    }
+}
+
+/// GET /respect_rules
+#[derive(Debug, Serialize)]
+pub struct RespectRulesView<'a> {
+    pub rules: &'a RespectRules,
+    pub allowance: RespectAllowance,
+}
+
+impl State {
+    pub fn respect_allowance(&self, now: SystemTime) -> RespectAllowance {
+        let last = self.respect_events.last().copied();
+        let since_last = last.map(|last| now.duration_since(last).unwrap_or_default());
+        let cooldown = Duration::from_secs(self.respect_rules.cooldown_secs);
+        let cooldown_left = since_last.map_or(Duration::ZERO, |since| cooldown.saturating_sub(since));
+        let today = Window::Daily.period(now);
+        let paid_today = self.respect_events.iter().filter(|at| Window::Daily.period(**at) == today).count() as u64;
+        let remaining_today = self.respect_rules.daily_cap.map(|cap| cap.saturating_sub(paid_today));
+        let cap_left = if remaining_today == Some(0) {
+            let into_day = now.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs() % SECS_PER_DAY;
+            Duration::from_secs(SECS_PER_DAY - into_day)
+        } else {
+            Duration::ZERO
+        };
+        RespectAllowance {
+            remaining_today,
+            retry_after_secs: cooldown_left.max(cap_left).as_secs_f64().ceil() as u64,
+            next_credit_percent: 100 >> self.rapid_repeats(now),
+        }
+    }
+
+    /// how many respects in a row, up to now, each came within the diminishing window of the one before
+    fn rapid_repeats(&self, now: SystemTime) -> usize {
+        let Some(window) = self.respect_rules.diminishing_secs.map(Duration::from_secs) else {
+            return 0;
+        };
+        let mut later = now;
+        let mut repeats = 0;
+        for at in self.respect_events.iter().rev() {
+            if later.duration_since(*at).map_or(false, |gap| gap >= window) || repeats >= MAX_DIMINISHING_STEPS {
+                break;
+            }
+            repeats += 1;
+            later = *at;
+        }
+        repeats
+    }
+
+    /// checks the rules for a respect paid at `now`. returns the share of its weight it scores
+    pub fn check_respect_allowed(&self, now: SystemTime) -> Result<u64, RespectRefused> {
+        let allowance = self.respect_allowance(now);
+        if allowance.remaining_today == Some(0) {
+            return Err(RespectRefused::DailyCap);
+        }
+        if allowance.retry_after_secs > 0 {
+            return Err(RespectRefused::Cooldown);
+        }
+        Ok(allowance.next_credit_percent)
+    }
+
+    pub fn set_respect_rules(&mut self, rules: RespectRules) -> Result<(), String> {
+        rules.validate()?;
+        self.respect_rules = rules;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(100 * SECS_PER_DAY + secs)
+    }
+
+    fn state(rules: RespectRules, events: &[u64]) -> State {
+        let mut state = State::new("alice.os".to_string());
+        state.respect_rules = rules;
+        state.respect_events = events.iter().map(|secs| at(*secs)).collect();
+        state
+    }
+
+    #[test]
+    fn cooldown_waits_out_the_last_respect() {
+        let state = state(RespectRules { cooldown_secs: 10, daily_cap: None, diminishing_secs: None }, &[100]);
+        assert_eq!(state.check_respect_allowed(at(105)), Err(RespectRefused::Cooldown));
+        assert_eq!(state.respect_allowance(at(105)).retry_after_secs, 5);
+        assert_eq!(state.check_respect_allowed(at(110)), Ok(100));
+    }
+
+    #[test]
+    fn daily_cap_resets_at_utc_midnight() {
+        let state = state(RespectRules { cooldown_secs: 0, daily_cap: Some(2), diminishing_secs: None }, &[100, 200]);
+        assert_eq!(state.check_respect_allowed(at(300)), Err(RespectRefused::DailyCap));
+        assert_eq!(state.respect_allowance(at(300)).retry_after_secs, SECS_PER_DAY - 300);
+        assert_eq!(state.respect_allowance(at(SECS_PER_DAY)).remaining_today, Some(2));
+    }
+
+    #[test]
+    fn rapid_repeats_halve_down_to_nothing() {
+        let rules = RespectRules { cooldown_secs: 0, daily_cap: None, diminishing_secs: Some(10) };
+        assert_eq!(state(rules.clone(), &[]).check_respect_allowed(at(0)), Ok(100));
+        assert_eq!(state(rules.clone(), &[95]).check_respect_allowed(at(100)), Ok(50));
+        assert_eq!(state(rules.clone(), &[80, 90, 95]).check_respect_allowed(at(100)), Ok(25));
+        // a gap of the full window ends the run
+        assert_eq!(state(rules.clone(), &[85, 95]).check_respect_allowed(at(105)), Ok(100));
+        let run: Vec<u64> = (0..20).collect();
+        assert_eq!(state(rules, &run).check_respect_allowed(at(20)), Ok(0));
+    }
+
+    #[test]
+    fn rules_are_validated() {
+        assert!(RespectRules::default().validate().is_ok());
+        assert!(RespectRules { daily_cap: Some(0), ..RespectRules::default() }.validate().is_err());
+        assert!(RespectRules { cooldown_secs: SECS_PER_DAY + 1, ..RespectRules::default() }.validate().is_err());
+        assert!(RespectRules { diminishing_secs: Some(0), ..RespectRules::default() }.validate().is_err());
+    }
+}
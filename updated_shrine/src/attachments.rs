@@ -0,0 +1,69 @@
+use kinode_process_lib::{vfs, NodeId};
+
+use crate::structs::Attachment;
+
+pub const MAX_ATTACHMENT_BYTES: usize = 5 * 1024 * 1024;
+pub const MAX_ATTACHMENTS_PER_MESSAGE: usize = 4;
+/// seconds we wait on the owner when fetching an attachment we don't have yet
+pub const ATTACHMENT_FETCH_TIMEOUT: u64 = 30;
+const MAX_NAME_CHARS: usize = 100;
+
+/// what we recognise from a file's first bytes. the uploader's claimed type is only checked against this
+pub fn sniff_mime(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else if std::str::from_utf8(bytes).is_ok() {
+        Some("text/plain")
+    } else {
+        None
+    }
+}
+
+/// the last path segment, without control characters, capped in length
+pub fn sanitize_name(name: &str) -> String {
+    let name: String = name.rsplit(['/', '\\']).next().unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_NAME_CHARS)
+        .collect();
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." {
+        "attachment".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// metadata on someone else's message is only believed if it's something we'd have accepted ourselves
+pub fn is_valid_remote(attachment: &Attachment, sender: &NodeId) -> bool {
+    &attachment.owner == sender
+        && attachment.size > 0
+        && attachment.size <= MAX_ATTACHMENT_BYTES
+        && !attachment.id.is_empty()
+        && attachment.id.chars().all(|c| c.is_ascii_hexdigit())
+        && matches!(attachment.mime.as_str(), "image/png" | "image/jpeg" | "image/gif" | "image/webp" | "application/pdf" | "text/plain")
+        && sanitize_name(&attachment.name) == attachment.name
+}
+
+/// one flat directory in our drive, files named by owner and id
+pub fn attachment_path(drive: &str, owner: &NodeId, id: &str) -> String {
+    format!("{}/{}-{}", drive, owner, id)
+}
+
+pub fn write_attachment(path: &str, bytes: &[u8]) -> Result<(), String> {
+    let file = vfs::open_file(path, true, None).map_err(|e| format!("{:?}", e))?;
+    file.write(bytes).map_err(|e| format!("{:?}", e))
+}
+
+pub fn read_attachment(path: &str) -> Option<Vec<u8>> {
+    let file = vfs::open_file(path, false, None).ok()?;
+    file.read().ok()
+}
@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use kinode_process_lib::NodeId;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use crate::leaderboard::{assign_ranks, LeaderboardQuery};
+use crate::structs::{LeaderboardEntry, State};
+
+pub const MAX_CONGREGATION_MEMBERS: usize = 50;
+const MAX_CONGREGATION_NAME_LEN: usize = 64;
+
+/// a named team on the leaderboard. like a room, the founder is the authority on membership:
+/// nobody is in one without accepting an invite, and the founder re-broadcasts every change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Congregation {
+    pub name: String,
+    pub founder: NodeId,
+    pub members: Vec<NodeId>, // includes the founder
+    pub invited: Vec<NodeId>, // only tracked by the founder
+    /// members' entries as the founder last relayed them, for members we don't hear from ourselves
+    pub entries: HashMap<NodeId, LeaderboardEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CongregationInvite {
+    pub name: String,
+    pub founder: NodeId,
+    pub members: Vec<NodeId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CongregationRequest {
+    CongregationInvite { congregation_id: String, name: String, members: Vec<NodeId> },
+    CongregationJoin { congregation_id: String },
+    CongregationLeave { congregation_id: String },
+    /// the founder took us out
+    CongregationRemoved { congregation_id: String },
+    CongregationMembership { congregation_id: String, members: Vec<NodeId>, entries: HashMap<NodeId, LeaderboardEntry> },
+    CongregationDisbanded { congregation_id: String },
+}
+
+/// POST /congregations
+#[derive(Debug, Deserialize)]
+pub enum CongregationBody {
+    Create { name: String },
+    Invite { congregation_id: String, node: NodeId },
+    Accept { congregation_id: String },
+    Decline { congregation_id: String },
+    /// the founder leaving disbands it
+    Leave { congregation_id: String },
+    Remove { congregation_id: String, node: NodeId },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RankedCongregation {
+    pub congregation_id: String,
+    pub name: String,
+    pub founder: NodeId,
+    pub members: Vec<NodeId>,
+    /// sum of the members' values on the requested board
+    pub total: u64,
+    pub rank: usize,
+    pub tied: bool,
+    pub includes_us: bool,
+    /// members we have no entry for yet, counted as 0
+    pub missing: Vec<NodeId>,
+}
+
+impl State {
+    /// congregations are keyed by founder and name, like rooms
+    pub fn create_congregation(&mut self, name: &str) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_CONGREGATION_NAME_LEN {
+            return Err(format!("congregation names are 1 to {} characters", MAX_CONGREGATION_NAME_LEN));
+        }
+        let congregation_id = format!("{}:{}", self.node_id, name);
+        if self.congregations.contains_key(&congregation_id) {
+            return Err(format!("we already founded {:?}", name));
+        }
+        self.congregations.insert(congregation_id.clone(), Congregation {
+            name: name.to_string(),
+            founder: self.node_id.clone(),
+            members: vec![self.node_id.clone()],
+            invited: Vec::new(),
+            entries: HashMap::new(),
+        });
+        Ok(congregation_id)
+    }
+
+    /// the congregation, if we founded it
+    pub fn founded_congregation_mut(&mut self, congregation_id: &str) -> Option<&mut Congregation> {
+        self.congregations.get_mut(congregation_id).filter(|congregation| congregation.founder == self.node_id)
+    }
+
+    /// turns a pending invite into a congregation, returning its founder
+    pub fn accept_congregation_invite(&mut self, congregation_id: &str) -> Option<NodeId> {
+        let invite = self.congregation_invites.remove(congregation_id)?;
+        let mut members = invite.members;
+        if !members.contains(&self.node_id) {
+            members.push(self.node_id.clone());
+        }
+        self.congregations.insert(congregation_id.to_string(), Congregation {
+            name: invite.name,
+            founder: invite.founder.clone(),
+            members,
+            invited: Vec::new(),
+            entries: HashMap::new(),
+        });
+        Some(invite.founder)
+    }
+
+    /// what the founder relays: every member entry it holds itself
+    pub fn congregation_entries(&self, congregation: &Congregation) -> HashMap<NodeId, LeaderboardEntry> {
+        congregation.members.iter()
+            .filter_map(|member| Some((member.clone(), self.stats.get(member)?.clone())))
+            .collect()
+    }
+
+    /// the freshest entry we have for a member: ours or a contact's first, then gossip, then the founder's relay
+    fn member_entry<'a>(&'a self, congregation: &'a Congregation, member: &NodeId) -> Option<&'a LeaderboardEntry> {
+        self.stats.get(member)
+            .or_else(|| self.gossip_entries.get(member).map(|gossip| &gossip.entry))
+            .or_else(|| congregation.entries.get(member))
+    }
+
+    /// every congregation we're in, ranked by the members' combined value on the same board as `query`
+    pub fn ranked_congregations(&self, query: &LeaderboardQuery) -> Vec<RankedCongregation> {
+        let now = SystemTime::now();
+        let mut rows: Vec<RankedCongregation> = self.congregations.iter()
+            .map(|(congregation_id, congregation)| {
+                let mut total = 0u64;
+                let mut missing = Vec::new();
+                for member in &congregation.members {
+                    match self.member_entry(congregation, member) {
+                        Some(entry) => total = total.saturating_add(self.board_value(entry, query, now)),
+                        None => missing.push(member.clone()),
+                    }
+                }
+                RankedCongregation {
+                    congregation_id: congregation_id.clone(),
+                    name: congregation.name.clone(),
+                    founder: congregation.founder.clone(),
+                    members: congregation.members.clone(),
+                    total,
+                    rank: 0,
+                    tied: false,
+                    includes_us: congregation.members.contains(&self.node_id),
+                    missing,
+                }
+            })
+            .collect();
+        rows.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.congregation_id.cmp(&b.congregation_id)));
+        let totals: Vec<u64> = rows.iter().map(|row| row.total).collect();
+        for (row, (rank, tied)) in rows.iter_mut().zip(assign_ranks(&totals, query.ranking)) {
+            row.rank = rank;
+            row.tied = tied;
+        }
+        rows
+    }
+}
@@ -0,0 +1,314 @@
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use kinode_process_lib::NodeId;
+use std::time::{Duration, SystemTime};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::structs::{ChatRequest, State};
+
+/// how long one of our per-contact keys is used before we replace it
+pub const KEY_ROTATION_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const KDF_INFO: &[u8] = b"td_shrine chat v1";
+
+/// one side of a contact's key agreement. the secret never leaves this node
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KeyPair {
+    secret: [u8; 32],
+    public: [u8; 32],
+    created_at: SystemTime,
+}
+
+impl std::fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "KeyPair {{ id: {} }}", key_id(&self.public))
+    }
+}
+
+impl KeyPair {
+    fn generate() -> Self {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        KeyPair { secret: secret.to_bytes(), public: public.to_bytes(), created_at: SystemTime::now() }
+    }
+}
+
+/// the current and the one previous key on each side, so messages sealed
+/// just before a rotation can still be opened after it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContactKeys {
+    ours: Option<KeyPair>,
+    our_previous: Option<KeyPair>,
+    theirs: Option<[u8; 32]>,
+    their_previous: Option<[u8; 32]>,
+}
+
+/// a ChatRequest encrypted for exactly one sender and recipient. the key ids say which
+/// of the two nodes' keys were combined, the sender and recipient themselves are authenticated data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedChat {
+    pub sender_key: String,
+    pub recipient_key: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// attachment bytes sealed like a chat request. the ciphertext travels as the blob, this as the body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedBlob {
+    pub sender_key: String,
+    pub recipient_key: String,
+    pub nonce: String,
+}
+
+impl ChatRequest {
+    /// requests that carry message text and must never travel in the clear once we share a key
+    pub fn carries_content(&self) -> bool {
+        matches!(self, ChatRequest::ChatMessageReceived(_) | ChatRequest::EditMessage { .. } | ChatRequest::HistoryResponse(_))
+    }
+}
+
+impl State {
+    /// contacts, room members and nodes with a contact request either way. nobody else gets a key from us
+    pub fn may_exchange_keys(&self, node: &NodeId) -> bool {
+        self.contacts.contains(node) || self.is_room_member(node)
+            || self.pending_contact_requests.contains(node) || self.incoming_contact_requests.contains(node)
+    }
+
+    pub fn has_session(&self, node: &NodeId) -> bool {
+        self.chat_keys.get(node).map_or(false, |keys| keys.ours.is_some() && keys.theirs.is_some())
+    }
+
+    /// our current public key for `node`, made on first use. returned as (hex key, id of theirs we hold)
+    pub fn our_public_key(&mut self, node: &NodeId) -> (String, Option<String>) {
+        let keys = self.chat_keys.entry(node.clone()).or_default();
+        let ours = keys.ours.get_or_insert_with(KeyPair::generate);
+        (to_hex(&ours.public), keys.theirs.as_ref().map(key_id))
+    }
+
+    /// stores the key `node` sent us. returns whether they need ours, because they
+    /// don't know it yet or know an older one
+    pub fn receive_public_key(&mut self, node: &NodeId, public_key: &str, known_key: Option<&str>) -> Result<bool, String> {
+        let public: [u8; 32] = from_hex(public_key)?.try_into().map_err(|_| "public key isn't 32 bytes".to_string())?;
+        let keys = self.chat_keys.entry(node.clone()).or_default();
+        if keys.theirs != Some(public) {
+            keys.their_previous = keys.theirs.replace(public);
+        }
+        let our_current = keys.ours.as_ref().map(|ours| key_id(&ours.public));
+        Ok(our_current.is_none() || our_current.as_deref() != known_key)
+    }
+
+    /// replaces every one of our keys older than the rotation interval. returns who needs the new ones
+    pub fn rotate_stale_keys(&mut self) -> Vec<NodeId> {
+        let mut rotated = Vec::new();
+        for (node, keys) in self.chat_keys.iter_mut() {
+            let stale = keys.ours.as_ref().map_or(false, |ours| {
+                ours.created_at.elapsed().map_or(false, |age| age >= KEY_ROTATION_INTERVAL)
+            });
+            if stale {
+                keys.our_previous = keys.ours.replace(KeyPair::generate());
+                rotated.push(node.clone());
+            }
+        }
+        rotated
+    }
+
+    pub fn forget_keys(&mut self, node: &NodeId) {
+        self.chat_keys.remove(node);
+    }
+
+    /// None until both sides' keys are in place
+    pub fn seal(&self, node: &NodeId, request: &ChatRequest) -> Option<SealedChat> {
+        let plaintext = serde_json::to_vec(request).ok()?;
+        let (sender_key, recipient_key, nonce, ciphertext) = self.encrypt(node, &plaintext, &associated_data(&self.node_id, node))?;
+        Some(SealedChat { sender_key, recipient_key, nonce, ciphertext: to_hex(&ciphertext) })
+    }
+
+    /// opens something `node` sealed for us or, with `outgoing`, something we sealed for `node`
+    pub fn open(&self, node: &NodeId, sealed: &SealedChat, outgoing: bool) -> Result<ChatRequest, String> {
+        let (our_key, their_key) = if outgoing { (&sealed.sender_key, &sealed.recipient_key) } else { (&sealed.recipient_key, &sealed.sender_key) };
+        let aad = if outgoing { associated_data(&self.node_id, node) } else { associated_data(node, &self.node_id) };
+        let plaintext = self.decrypt(node, our_key, their_key, &sealed.nonce, &from_hex(&sealed.ciphertext)?, &aad)?;
+        serde_json::from_slice(&plaintext).map_err(|e| e.to_string())
+    }
+
+    /// seals the bytes of attachment `id` for `node`. returns the header and the ciphertext
+    pub fn seal_blob(&self, node: &NodeId, id: &str, bytes: &[u8]) -> Option<(SealedBlob, Vec<u8>)> {
+        let aad = blob_associated_data(&self.node_id, node, id);
+        let (sender_key, recipient_key, nonce, ciphertext) = self.encrypt(node, bytes, &aad)?;
+        Some((SealedBlob { sender_key, recipient_key, nonce }, ciphertext))
+    }
+
+    /// opens the bytes of attachment `id` that `node` sealed for us
+    pub fn open_blob(&self, node: &NodeId, id: &str, sealed: &SealedBlob, ciphertext: &[u8]) -> Result<Vec<u8>, String> {
+        let aad = blob_associated_data(node, &self.node_id, id);
+        self.decrypt(node, &sealed.recipient_key, &sealed.sender_key, &sealed.nonce, ciphertext, &aad)
+    }
+
+    // with our current keys. returns (our key id, their key id, hex nonce, ciphertext)
+    fn encrypt(&self, node: &NodeId, plaintext: &[u8], aad: &[u8]) -> Option<(String, String, String, Vec<u8>)> {
+        let keys = self.chat_keys.get(node)?;
+        let (ours, theirs) = (keys.ours.as_ref()?, keys.theirs.as_ref()?);
+        let cipher = XChaCha20Poly1305::new_from_slice(&derive_key(ours, theirs, &self.node_id, node)?).ok()?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher.encrypt(&nonce, Payload { msg: plaintext, aad }).ok()?;
+        Some((key_id(&ours.public), key_id(theirs), to_hex(&nonce), ciphertext))
+    }
+
+    // with whichever of the current and previous keys the ids name
+    fn decrypt(&self, node: &NodeId, our_key: &str, their_key: &str, nonce: &str, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+        let keys = self.chat_keys.get(node).ok_or("no keys for this node")?;
+        let ours = [&keys.ours, &keys.our_previous].into_iter().flatten()
+            .find(|ours| key_id(&ours.public) == our_key)
+            .ok_or("sealed for a key of ours we don't have")?;
+        let theirs = [&keys.theirs, &keys.their_previous].into_iter().flatten()
+            .find(|theirs| key_id(theirs) == their_key)
+            .ok_or("sealed with a key of theirs we don't have")?;
+        let key = derive_key(ours, theirs, &self.node_id, node).ok_or("key agreement failed")?;
+        let cipher = XChaCha20Poly1305::new_from_slice(&key).map_err(|e| e.to_string())?;
+        let nonce = from_hex(nonce)?;
+        if nonce.len() != 24 {
+            return Err("bad nonce".to_string());
+        }
+        cipher
+            .decrypt(XNonce::from_slice(&nonce), Payload { msg: ciphertext, aad })
+            .map_err(|_| "authentication failed".to_string())
+    }
+}
+
+/// both nodes end up with the same key: the shared secret is symmetric and the node ids are sorted
+fn derive_key(ours: &KeyPair, theirs: &[u8; 32], our_node: &NodeId, their_node: &NodeId) -> Option<[u8; 32]> {
+    let shared = StaticSecret::from(ours.secret).diffie_hellman(&PublicKey::from(*theirs));
+    // a low-order public key would force a known secret
+    if !shared.was_contributory() {
+        return None;
+    }
+    let (first, second) = if our_node <= their_node { (our_node, their_node) } else { (their_node, our_node) };
+    let info = [KDF_INFO, first.as_bytes(), &[0], second.as_bytes()].concat();
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, shared.as_bytes()).expand(&info, &mut key).ok()?;
+    Some(key)
+}
+
+// binds the direction, so a sealed request can't be bounced back or replayed to someone else
+fn associated_data(sender: &NodeId, recipient: &NodeId) -> Vec<u8> {
+    [sender.as_bytes(), &[0], recipient.as_bytes()].concat()
+}
+
+// also binds the attachment, so one sealed file can't be passed off as another
+fn blob_associated_data(sender: &NodeId, recipient: &NodeId, id: &str) -> Vec<u8> {
+    [associated_data(sender, recipient).as_slice(), b"\0attachment\0", id.as_bytes()].concat()
+}
+
+fn key_id(public: &[u8; 32]) -> String {
+    to_hex(&Sha256::digest(public)[..8])
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err("bad hex".to_string());
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| "bad hex".to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::ConversationId;
+
+    fn node(name: &str) -> NodeId {
+        name.to_string()
+    }
+
+    // two nodes that have traded keys both ways
+    fn pair() -> (State, State) {
+        let (mut alice, mut bob) = (State::new(node("alice.os")), State::new(node("bob.os")));
+        exchange(&mut alice, &mut bob);
+        exchange(&mut bob, &mut alice);
+        (alice, bob)
+    }
+
+    // `from` sends its current key to `to`
+    fn exchange(from: &mut State, to: &mut State) {
+        let (public_key, known_key) = from.our_public_key(&to.node_id);
+        to.receive_public_key(&from.node_id, &public_key, known_key.as_deref()).unwrap();
+    }
+
+    fn typing() -> ChatRequest {
+        ChatRequest::Typing { conversation: ConversationId::Shrine }
+    }
+
+    fn is_typing(request: ChatRequest) -> bool {
+        matches!(request, ChatRequest::Typing { conversation: ConversationId::Shrine })
+    }
+
+    #[test]
+    fn derive_key_is_symmetric() {
+        let (ours, theirs) = (KeyPair::generate(), KeyPair::generate());
+        let (a, b) = (node("alice.os"), node("bob.os"));
+        let ab = derive_key(&ours, &theirs.public, &a, &b).unwrap();
+        let ba = derive_key(&theirs, &ours.public, &b, &a).unwrap();
+        assert_eq!(ab, ba);
+        assert_ne!(ab, derive_key(&ours, &theirs.public, &a, &node("carol.os")).unwrap());
+    }
+
+    #[test]
+    fn seal_opens_both_ways() {
+        let (alice, bob) = pair();
+        let sealed = alice.seal(&bob.node_id, &typing()).unwrap();
+        assert!(is_typing(bob.open(&alice.node_id, &sealed, false).unwrap()));
+        assert!(is_typing(alice.open(&bob.node_id, &sealed, true).unwrap()));
+
+        let sealed = bob.seal(&alice.node_id, &typing()).unwrap();
+        assert!(is_typing(alice.open(&bob.node_id, &sealed, false).unwrap()));
+        assert!(is_typing(bob.open(&alice.node_id, &sealed, true).unwrap()));
+    }
+
+    #[test]
+    fn opens_with_their_previous_key_after_rotation() {
+        let (mut alice, mut bob) = pair();
+        let sealed = alice.seal(&bob.node_id, &typing()).unwrap();
+
+        if let Some(ours) = alice.chat_keys.get_mut(&bob.node_id).and_then(|keys| keys.ours.as_mut()) {
+            ours.created_at = SystemTime::UNIX_EPOCH;
+        }
+        assert_eq!(alice.rotate_stale_keys(), vec![bob.node_id.clone()]);
+        exchange(&mut alice, &mut bob);
+
+        assert!(is_typing(bob.open(&alice.node_id, &sealed, false).unwrap()));
+        let fresh = alice.seal(&bob.node_id, &typing()).unwrap();
+        assert_ne!(fresh.sender_key, sealed.sender_key);
+        assert!(is_typing(bob.open(&alice.node_id, &fresh, false).unwrap()));
+    }
+
+    #[test]
+    fn rejects_the_wrong_direction() {
+        let (alice, bob) = pair();
+        let sealed = alice.seal(&bob.node_id, &typing()).unwrap();
+        // the same keys read the other way round, only the authenticated data tells them apart
+        let bounced = SealedChat { sender_key: sealed.recipient_key.clone(), recipient_key: sealed.sender_key.clone(), ..sealed.clone() };
+        assert!(bob.open(&alice.node_id, &bounced, true).is_err());
+        assert!(alice.open(&bob.node_id, &bounced, false).is_err());
+
+        let (mut carol, mut dave) = (State::new(node("carol.os")), State::new(node("dave.os")));
+        exchange(&mut carol, &mut dave);
+        exchange(&mut dave, &mut carol);
+        assert!(dave.open(&carol.node_id, &sealed, false).is_err());
+    }
+
+    #[test]
+    fn blobs_are_bound_to_their_attachment() {
+        let (alice, bob) = pair();
+        let (sealed, ciphertext) = alice.seal_blob(&bob.node_id, "alice.os:1", b"bytes").unwrap();
+        assert_eq!(bob.open_blob(&alice.node_id, "alice.os:1", &sealed, &ciphertext).unwrap(), b"bytes");
+        assert!(bob.open_blob(&alice.node_id, "alice.os:2", &sealed, &ciphertext).is_err());
+    }
+}
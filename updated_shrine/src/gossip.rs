@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+use kinode_process_lib::NodeId;
+use std::time::{Duration, SystemTime};
+
+use crate::structs::{LeaderboardEntry, State};
+
+/// entries travel at most this many contacts away from their origin
+pub const MAX_GOSSIP_HOPS: usize = 3;
+/// entries we haven't heard fresh news of for this long fall off the extended board
+pub const GOSSIP_TTL: Duration = Duration::from_secs(60 * 60);
+/// per gossip message, so one contact can't flood us
+pub const MAX_GOSSIP_ENTRIES: usize = 500;
+
+/// someone's entry as it was passed along to us
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GossipEntry {
+    pub origin: NodeId,
+    pub entry: LeaderboardEntry,
+    /// origin first, then everyone who passed it on, ending with whoever sent it to us
+    pub path: Vec<NodeId>,
+    /// when the origin's entry was last known to be current
+    pub updated_at: SystemTime,
+    /// the origin's discoverability, only discoverable entries are passed on past their contacts
+    pub shareable: bool,
+}
+
+impl GossipEntry {
+    /// 1 for one of our contacts, 2 for one of theirs, ...
+    pub fn hops(&self) -> usize {
+        self.path.len()
+    }
+}
+
+impl State {
+    /// what we tell `contact`: our own entry, our contacts' and everything else shareable that still
+    /// has hops left, minus what they'd only be hearing back about themselves
+    pub fn gossip_for(&self, contact: &NodeId) -> Vec<GossipEntry> {
+        let now = SystemTime::now();
+        let ours = GossipEntry {
+            origin: self.node_id.clone(),
+            entry: self.stats.get(&self.node_id).cloned().unwrap_or_default(),
+            path: vec![self.node_id.clone()],
+            updated_at: now,
+            shareable: self.discoverable,
+        };
+        // our contacts' own entries, if they let us pass them on
+        let contacts = self.contacts.iter()
+            .filter(|node| self.gossip_shareable.get(*node) == Some(&true))
+            .filter_map(|node| Some(GossipEntry {
+                origin: node.clone(),
+                entry: self.stats.get(node)?.clone(),
+                path: vec![node.clone(), self.node_id.clone()],
+                updated_at: *self.stats_updated_at.get(node)?,
+                shareable: true,
+            }));
+        let passed_on = self.gossip_entries.values()
+            .filter(|gossip| gossip.shareable && gossip.hops() < MAX_GOSSIP_HOPS && !gossip.path.contains(contact))
+            .map(|gossip| {
+                let mut gossip = gossip.clone();
+                gossip.path.push(self.node_id.clone());
+                gossip
+            });
+        std::iter::once(ours)
+            .chain(contacts)
+            .chain(passed_on)
+            .filter(|gossip| &gossip.origin != contact)
+            .take(MAX_GOSSIP_ENTRIES)
+            .collect()
+    }
+
+    /// keeps the freshest version of each entry, and the shortest path among equally fresh ones.
+    /// direct contacts' entries are only used for their sharing policy, their scores come from ContactUpdate
+    pub fn merge_gossip(&mut self, sender: &NodeId, entries: Vec<GossipEntry>) -> usize {
+        let now = SystemTime::now();
+        let mut merged = 0;
+        for mut gossip in entries.into_iter().take(MAX_GOSSIP_ENTRIES) {
+            let valid_path = gossip.path.first() == Some(&gossip.origin)
+                && gossip.path.last() == Some(sender)
+                && gossip.hops() <= MAX_GOSSIP_HOPS
+                && !gossip.path.contains(&self.node_id);
+            if !valid_path {
+                continue;
+            }
+            if &gossip.origin == sender {
+                self.gossip_shareable.insert(sender.clone(), gossip.shareable);
+                continue;
+            }
+            if !gossip.shareable || self.contacts.contains(&gossip.origin) || self.deleted_entries.contains(&gossip.origin) {
+                continue;
+            }
+            // a clock ahead of ours doesn't get to make an entry look fresher than now
+            gossip.updated_at = gossip.updated_at.min(now);
+            let better = match self.gossip_entries.get(&gossip.origin) {
+                Some(known) => gossip.updated_at > known.updated_at
+                    || (gossip.updated_at == known.updated_at && gossip.hops() < known.hops()),
+                None => true,
+            };
+            if better {
+                self.gossip_entries.insert(gossip.origin.clone(), gossip);
+                merged += 1;
+            }
+        }
+        merged
+    }
+
+    /// drops stale entries and those whose origin turned out to be a direct contact
+    pub fn prune_gossip(&mut self) {
+        let contacts = &self.contacts;
+        self.gossip_entries.retain(|origin, gossip| {
+            !contacts.contains(origin) && gossip.updated_at.elapsed().map_or(true, |age| age < GOSSIP_TTL)
+        });
+        self.gossip_shareable.retain(|node, _| contacts.contains(node));
+        self.stats_updated_at.retain(|node, _| contacts.contains(node));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str) -> NodeId {
+        name.to_string()
+    }
+
+    fn gossip(path: &[&str], updated_at: SystemTime) -> GossipEntry {
+        GossipEntry {
+            origin: node(path[0]),
+            entry: LeaderboardEntry::default(),
+            path: path.iter().map(|hop| node(hop)).collect(),
+            updated_at,
+            shareable: true,
+        }
+    }
+
+    fn alice() -> State {
+        let mut state = State::new(node("alice.os"));
+        state.contacts = vec![node("bob.os"), node("carol.os")];
+        state
+    }
+
+    #[test]
+    fn paths_must_run_from_origin_to_sender_within_the_hop_limit() {
+        let mut state = alice();
+        let now = SystemTime::now();
+        let entries = vec![
+            gossip(&["dave.os", "bob.os"], now),
+            // ends with someone other than the sender
+            gossip(&["erin.os", "carol.os"], now),
+            // one hop too many
+            gossip(&["frank.os", "x.os", "y.os", "bob.os"], now),
+            // we're already on it
+            gossip(&["gina.os", "alice.os", "bob.os"], now),
+        ];
+        assert_eq!(state.merge_gossip(&node("bob.os"), entries), 1);
+        assert_eq!(state.gossip_entries.keys().collect::<Vec<_>>(), vec!["dave.os"]);
+    }
+
+    #[test]
+    fn fresher_or_shorter_wins() {
+        let mut state = alice();
+        let then = SystemTime::now() - Duration::from_secs(60);
+        assert_eq!(state.merge_gossip(&node("bob.os"), vec![gossip(&["dave.os", "x.os", "bob.os"], then)]), 1);
+        // same age, shorter path
+        assert_eq!(state.merge_gossip(&node("carol.os"), vec![gossip(&["dave.os", "carol.os"], then)]), 1);
+        assert_eq!(state.gossip_entries["dave.os"].hops(), 2);
+        // same age, longer path
+        assert_eq!(state.merge_gossip(&node("bob.os"), vec![gossip(&["dave.os", "x.os", "bob.os"], then)]), 0);
+        // fresher, even if longer
+        let later = then + Duration::from_secs(30);
+        assert_eq!(state.merge_gossip(&node("bob.os"), vec![gossip(&["dave.os", "x.os", "bob.os"], later)]), 1);
+        assert_eq!(state.gossip_entries["dave.os"].updated_at, later);
+    }
+
+    #[test]
+    fn future_timestamps_are_clamped_and_contacts_only_set_their_policy() {
+        let mut state = alice();
+        let future = SystemTime::now() + Duration::from_secs(3600);
+        let mut own = gossip(&["bob.os"], future);
+        own.shareable = false;
+        state.merge_gossip(&node("bob.os"), vec![gossip(&["dave.os", "bob.os"], future), gossip(&["carol.os", "bob.os"], future), own]);
+        assert!(state.gossip_entries["dave.os"].updated_at <= SystemTime::now());
+        assert!(!state.gossip_entries.contains_key("carol.os"));
+        assert_eq!(state.gossip_shareable.get("bob.os"), Some(&false));
+    }
+
+    #[test]
+    fn stale_entries_are_pruned() {
+        let mut state = alice();
+        let stale = SystemTime::now() - GOSSIP_TTL - Duration::from_secs(1);
+        state.merge_gossip(&node("bob.os"), vec![gossip(&["dave.os", "bob.os"], stale), gossip(&["erin.os", "bob.os"], SystemTime::now())]);
+        state.prune_gossip();
+        assert_eq!(state.gossip_entries.keys().collect::<Vec<_>>(), vec!["erin.os"]);
+    }
+
+    #[test]
+    fn passed_on_gossip_gains_a_hop_and_stops_at_the_limit() {
+        let mut state = alice();
+        let now = SystemTime::now();
+        state.merge_gossip(&node("bob.os"), vec![gossip(&["dave.os", "bob.os"], now), gossip(&["erin.os", "x.os", "bob.os"], now)]);
+        let for_carol = state.gossip_for(&node("carol.os"));
+        let dave = for_carol.iter().find(|gossip| gossip.origin == "dave.os").unwrap();
+        assert_eq!(dave.path, vec![node("dave.os"), node("bob.os"), node("alice.os")]);
+        assert!(!for_carol.iter().any(|gossip| gossip.origin == "erin.os"));
+        // nobody hears back about entries that already went through them
+        assert!(!state.gossip_for(&node("bob.os")).iter().any(|gossip| gossip.origin == "dave.os"));
+    }
+}
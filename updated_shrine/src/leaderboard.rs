@@ -0,0 +1,345 @@
+use serde::{Deserialize, Serialize};
+use kinode_process_lib::NodeId;
+use std::collections::{BTreeMap, HashMap};
+use std::time::SystemTime;
+
+use crate::achievements::AchievementKind;
+use crate::congregations::RankedCongregation;
+use crate::offerings::Offering;
+use crate::structs::{LeaderboardEntry, State};
+use crate::windows::Window;
+
+pub const DEFAULT_LEADERBOARD_PAGE_SIZE: usize = 25;
+pub const MAX_LEADERBOARD_PAGE_SIZE: usize = 100;
+
+/// how tied nodes share a rank: competition gives 1, 2, 2, 4 and dense gives 1, 2, 2, 3
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RankStyle {
+    #[default]
+    Competition,
+    Dense,
+}
+
+/// who's on the board: us and our contacts, or also whoever reached us through gossip
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    #[default]
+    Contacts,
+    Extended,
+}
+
+/// what the board is ordered by. the weighted score and the per-offering counts are all-time
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum SortBy {
+    #[default]
+    Respects,
+    Score,
+    Offering(String),
+}
+
+impl std::fmt::Display for SortBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortBy::Respects => write!(f, "respects"),
+            SortBy::Score => write!(f, "score"),
+            SortBy::Offering(offering) => write!(f, "offering:{}", offering),
+        }
+    }
+}
+
+/// /leaderboard?window=daily|weekly|monthly|all_time&sort=respects|score|<offering>
+/// &ranking=competition|dense&scope=contacts|extended&page=1&per_page=25
+#[derive(Debug)]
+pub struct LeaderboardQuery {
+    pub window: Window,
+    pub sort: SortBy,
+    pub ranking: RankStyle,
+    pub scope: Scope,
+    pub page: usize, // 1-based
+    pub per_page: usize,
+}
+
+/// the main board: contacts, all-time respects, competition ranking, first page
+impl Default for LeaderboardQuery {
+    fn default() -> Self {
+        LeaderboardQuery {
+            window: Window::default(),
+            sort: SortBy::default(),
+            ranking: RankStyle::default(),
+            scope: Scope::default(),
+            page: 1,
+            per_page: DEFAULT_LEADERBOARD_PAGE_SIZE,
+        }
+    }
+}
+
+impl LeaderboardQuery {
+    /// any sort besides respects and score has to name an offering in `catalogue`
+    pub fn from_params(params: &HashMap<String, String>, catalogue: &BTreeMap<String, Offering>) -> Result<Self, String> {
+        let window = params.get("window").map(|value| Window::from_param(value)).transpose()?.unwrap_or_default();
+        let sort = match params.get("sort").map(String::as_str) {
+            None | Some("respects") => SortBy::Respects,
+            Some("score") => SortBy::Score,
+            Some(offering) if catalogue.contains_key(offering) => SortBy::Offering(offering.to_string()),
+            Some(other) => return Err(format!("bad sort {other:?}")),
+        };
+        if sort != SortBy::Respects && window != Window::AllTime {
+            return Err(format!("sorting by {} only works on the all_time window", sort));
+        }
+        let ranking = match params.get("ranking").map(String::as_str) {
+            None | Some("competition") => RankStyle::Competition,
+            Some("dense") => RankStyle::Dense,
+            Some(other) => return Err(format!("bad ranking {other:?}")),
+        };
+        let scope = match params.get("scope").map(String::as_str) {
+            None | Some("contacts") => Scope::Contacts,
+            Some("extended") => Scope::Extended,
+            Some(other) => return Err(format!("bad scope {other:?}")),
+        };
+        let page = match params.get("page") {
+            Some(value) => value.parse::<usize>().ok().filter(|page| *page > 0).ok_or(format!("bad page {value:?}"))?,
+            None => 1,
+        };
+        let per_page = match params.get("per_page") {
+            Some(value) => value.parse::<usize>().map_err(|_| format!("bad per_page {value:?}"))?.clamp(1, MAX_LEADERBOARD_PAGE_SIZE),
+            None => DEFAULT_LEADERBOARD_PAGE_SIZE,
+        };
+        Ok(LeaderboardQuery { window, sort, ranking, scope, page, per_page })
+    }
+
+    /// deltas are kept per window and ordering, so switching boards doesn't mix them up
+    fn board_key(&self) -> String {
+        format!("{:?}/{}", self.window, self.sort)
+    }
+}
+
+/// where a node stood the last time the UI was shown it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeenRank {
+    pub rank: usize,
+    pub value: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RankedEntry {
+    pub node: NodeId,
+    pub respects: u64, // within the requested window
+    /// weighted by our offering catalogue
+    pub score: u64,
+    pub offerings: BTreeMap<String, u64>,
+    /// what the board is sorted by: respects, score or one offering's count
+    pub value: u64,
+    pub rank: usize,
+    /// shares its rank with at least one other node
+    pub tied: bool,
+    pub is_us: bool,
+    /// change in value since this node was last shown on this board, None the first time
+    pub value_delta: Option<i64>,
+    /// positive is moving up the board
+    pub rank_delta: Option<i64>,
+    /// 0 for us, 1 for our contacts, more for entries that came through gossip
+    pub hops: usize,
+    /// for gossiped entries, who it came through: origin first, our contact last
+    pub path: Vec<NodeId>,
+    /// when the entry was last known to be current. None for our own, which always is
+    pub updated_at: Option<SystemTime>,
+    pub streak: u32,
+    pub achievements: Vec<AchievementKind>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaderboardPage {
+    pub entries: Vec<RankedEntry>,
+    /// our own entry wherever it is, so the UI can pin it even when it's on another page
+    pub ours: Option<RankedEntry>,
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+    pub pages: usize,
+    /// the congregations we're in, totalled on the same board
+    pub congregations: Vec<RankedCongregation>,
+}
+
+/// ranks for scores already sorted from highest to lowest, with whether each one is tied
+pub fn assign_ranks(scores: &[u64], style: RankStyle) -> Vec<(usize, bool)> {
+    let mut ranks = Vec::with_capacity(scores.len());
+    let mut rank = 0;
+    for (i, score) in scores.iter().enumerate() {
+        if i == 0 || scores[i - 1] != *score {
+            rank = match style {
+                RankStyle::Competition => i + 1,
+                RankStyle::Dense => rank + 1,
+            };
+        }
+        let tied = (i > 0 && scores[i - 1] == *score) || scores.get(i + 1) == Some(score);
+        ranks.push((rank, tied));
+    }
+    ranks
+}
+
+impl State {
+    /// what `entry` counts for on the board `query` asks for
+    pub fn board_value(&self, entry: &LeaderboardEntry, query: &LeaderboardQuery, now: SystemTime) -> u64 {
+        match &query.sort {
+            SortBy::Respects => entry.respects_in(query.window, now),
+            SortBy::Score => self.weighted_score(entry),
+            SortBy::Offering(offering) => entry.offerings.get(offering).copied().unwrap_or(0),
+        }
+    }
+
+    fn ranked_row(&self, node: &NodeId, entry: &LeaderboardEntry, query: &LeaderboardQuery, now: SystemTime) -> RankedEntry {
+        RankedEntry {
+            node: node.clone(),
+            respects: entry.respects_in(query.window, now),
+            score: self.weighted_score(entry),
+            offerings: entry.offerings.clone(),
+            value: self.board_value(entry, query, now),
+            rank: 0,
+            tied: false,
+            is_us: node == &self.node_id,
+            value_delta: None,
+            rank_delta: None,
+            hops: 0,
+            path: Vec::new(),
+            updated_at: None,
+            streak: entry.streak.current_at(now),
+            achievements: entry.achievements.iter().map(|achievement| achievement.kind).collect(),
+        }
+    }
+
+    /// every entry, highest value first. ties are ordered by node so pages don't shuffle
+    pub fn ranked_leaderboard(&self, query: &LeaderboardQuery) -> Vec<RankedEntry> {
+        let now = SystemTime::now();
+        let seen = self.leaderboard_seen.get(&query.board_key());
+        let mut rows: Vec<RankedEntry> = self.stats.iter()
+            .filter(|(node, _)| self.is_listed(node))
+            .map(|(node, entry)| {
+                let mut row = self.ranked_row(node, entry, query, now);
+                if !row.is_us {
+                    row.hops = 1;
+                    row.path = vec![node.clone()];
+                    row.updated_at = self.stats_updated_at.get(node).copied();
+                }
+                row
+            })
+            .collect();
+        if query.scope == Scope::Extended {
+            rows.extend(self.gossip_entries.values()
+                .filter(|gossip| !self.stats.contains_key(&gossip.origin) && self.is_listed(&gossip.origin))
+                .map(|gossip| RankedEntry {
+                    hops: gossip.hops(),
+                    path: gossip.path.clone(),
+                    updated_at: Some(gossip.updated_at),
+                    ..self.ranked_row(&gossip.origin, &gossip.entry, query, now)
+                }));
+        }
+        rows.sort_by(|a, b| b.value.cmp(&a.value).then_with(|| a.node.cmp(&b.node)));
+        let values: Vec<u64> = rows.iter().map(|row| row.value).collect();
+        for (row, (rank, tied)) in rows.iter_mut().zip(assign_ranks(&values, query.ranking)) {
+            let seen = seen.and_then(|seen| seen.get(&row.node));
+            row.rank = rank;
+            row.tied = tied;
+            row.value_delta = seen.map(|seen| row.value as i64 - seen.value as i64);
+            row.rank_delta = seen.map(|seen| seen.rank as i64 - rank as i64);
+        }
+        rows
+    }
+
+    /// one page of the board. everything returned counts as seen, so the next deltas start from here
+    pub fn leaderboard_page(&mut self, query: &LeaderboardQuery) -> LeaderboardPage {
+        let ranked = self.ranked_leaderboard(query);
+        let total = ranked.len();
+        let ours = ranked.iter().find(|entry| entry.is_us).cloned();
+        let entries: Vec<RankedEntry> = ranked.into_iter()
+            .skip(query.page.saturating_sub(1).saturating_mul(query.per_page))
+            .take(query.per_page)
+            .collect();
+        let seen = self.leaderboard_seen.entry(query.board_key()).or_default();
+        for entry in entries.iter().chain(ours.iter()) {
+            seen.insert(entry.node.clone(), SeenRank { rank: entry.rank, value: entry.value });
+        }
+        LeaderboardPage {
+            entries,
+            ours,
+            total,
+            page: query.page,
+            per_page: query.per_page,
+            pages: total.div_ceil(query.per_page),
+            congregations: self.ranked_congregations(query),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn state_with_respects(respects: &[(&str, u64)]) -> State {
+        let mut state = State::new("alice.os".to_string());
+        for (node, respects) in respects {
+            if *node != "alice.os" {
+                state.contacts.push(node.to_string());
+            }
+            state.stats.insert(node.to_string(), LeaderboardEntry { respects: *respects, ..LeaderboardEntry::default() });
+        }
+        state
+    }
+
+    #[test]
+    fn competition_ranks_skip_after_ties() {
+        assert_eq!(assign_ranks(&[9, 7, 7, 4, 4, 1], RankStyle::Competition), vec![
+            (1, false), (2, true), (2, true), (4, true), (4, true), (6, false),
+        ]);
+    }
+
+    #[test]
+    fn dense_ranks_dont_skip() {
+        assert_eq!(assign_ranks(&[9, 7, 7, 4, 4, 1], RankStyle::Dense), vec![
+            (1, false), (2, true), (2, true), (3, true), (3, true), (4, false),
+        ]);
+        assert!(assign_ranks(&[], RankStyle::Dense).is_empty());
+        assert_eq!(assign_ranks(&[0, 0], RankStyle::Competition), vec![(1, true), (1, true)]);
+    }
+
+    #[test]
+    fn ties_break_by_node_and_pages_split_the_board() {
+        let mut state = state_with_respects(&[("alice.os", 5), ("bob.os", 8), ("carol.os", 5), ("dave.os", 1)]);
+        let query = LeaderboardQuery { per_page: 2, page: 2, ..LeaderboardQuery::default() };
+        let page = state.leaderboard_page(&query);
+        assert_eq!((page.total, page.pages), (4, 2));
+        let rows: Vec<(&str, usize, bool)> = page.entries.iter().map(|entry| (entry.node.as_str(), entry.rank, entry.tied)).collect();
+        assert_eq!(rows, vec![("carol.os", 2, true), ("dave.os", 4, false)]);
+        assert_eq!(page.ours.map(|ours| ours.rank), Some(2));
+    }
+
+    #[test]
+    fn pages_past_the_end_are_empty() {
+        let mut state = state_with_respects(&[("alice.os", 5), ("bob.os", 8)]);
+        let query = LeaderboardQuery { page: usize::MAX, per_page: MAX_LEADERBOARD_PAGE_SIZE, ..LeaderboardQuery::default() };
+        assert!(state.leaderboard_page(&query).entries.is_empty());
+    }
+
+    #[test]
+    fn bad_params_are_refused() {
+        let catalogue = BTreeMap::new();
+        assert!(LeaderboardQuery::from_params(&params(&[("page", "0")]), &catalogue).is_err());
+        assert!(LeaderboardQuery::from_params(&params(&[("ranking", "olympic")]), &catalogue).is_err());
+        assert!(LeaderboardQuery::from_params(&params(&[("scope", "world")]), &catalogue).is_err());
+        let query = LeaderboardQuery::from_params(&params(&[("per_page", "1000"), ("ranking", "dense")]), &catalogue).unwrap();
+        assert_eq!((query.per_page, query.ranking), (MAX_LEADERBOARD_PAGE_SIZE, RankStyle::Dense));
+    }
+
+    #[test]
+    fn sorts_must_name_a_known_offering() {
+        let catalogue = crate::offerings::default_catalogue();
+        let all_time = |sort: &str| LeaderboardQuery::from_params(&params(&[("sort", sort), ("window", "all_time")]), &catalogue);
+        assert_eq!(all_time("score").unwrap().sort, SortBy::Score);
+        assert_eq!(all_time("flower").unwrap().sort, SortBy::Offering("flower".to_string()));
+        assert!(all_time("scroe").is_err());
+        assert!(LeaderboardQuery::from_params(&params(&[("sort", "flower"), ("window", "daily")]), &catalogue).is_err());
+    }
+}
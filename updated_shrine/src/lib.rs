@@ -1,13 +1,41 @@
 use std::collections::HashMap;
 use std::str::FromStr;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use kinode_process_lib::{
-    Address, NodeId, Message, ProcessId, Request, Response, await_message, call_init, http, get_blob, clear_state, println,
+    Address, NodeId, Message, ProcessId, Request, Response, LazyLoadBlob, SendError, await_message, call_init, http, get_blob, clear_state, println, vfs,
     http::{bind_http_path, bind_ws_path, send_response, send_ws_push, serve_ui},
 };
 
 mod structs;
-use structs::{LeaderboardEntry, State, ContactRequest, ContactRequestBody, ChatMessage, ChatMessageBody, ChatRequest};
+mod attachments;
+mod query;
+mod oracle;
+mod moderation;
+mod offerings;
+mod allowance;
+mod congregations;
+mod standings;
+mod crypto;
+mod leaderboard;
+mod gossip;
+mod windows;
+mod achievements;
+mod search;
+mod validation;
+use structs::{State, ContactRequest, ContactRequestBody, RemoveEntryBody, ChatMessage, ChatMessageBody, ChatRequest, WsUpdate, DeliveryStatus, ConversationId, MarkReadBody, TypingBody,
+    RoomRequest, RoomEvent, RoomInvite, CreateRoomBody, RoomBody, RoomMemberBody,
+    EditChatMessageBody, DeleteChatMessageBody, ReactionBody, Attachment, AttachmentRequest, AttachmentResponse, TYPING_TTL, DELIVERY_TIMEOUT};
+use attachments::{MAX_ATTACHMENT_BYTES, MAX_ATTACHMENTS_PER_MESSAGE, ATTACHMENT_FETCH_TIMEOUT, sniff_mime, sanitize_name,
+    attachment_path, write_attachment, read_attachment};
+use query::{ChatQuery, parse_conversation};
+use search::{DEFAULT_SEARCH_LIMIT, MAX_SEARCH_LIMIT};
+use oracle::{god_command, revelation_from_params};
+use moderation::ModerationBody;
+use offerings::{AddRespectBody, OfferingBody, DEFAULT_OFFERING};
+use allowance::{RespectRefused, RespectRules, RespectRulesView};
+use congregations::{CongregationBody, CongregationInvite, CongregationRequest, MAX_CONGREGATION_MEMBERS};
+use leaderboard::LeaderboardQuery;
+use validation::{ContentError, sanitize_content, sanitize_remote_message};
 
 wit_bindgen::generate!({
     path: "wit",
@@ -20,26 +48,60 @@ fn init(our: Address) {
 
     clear_state();
     let mut state = State::fetch(our.node().to_string());
+    state.update_standings();
 
     serve_ui(&our, "ui", true, true, vec!["/"]).unwrap();
 
+    match vfs::create_drive(our.package_id(), "attachments", None) {
+        Ok(drive) => state.attachments_drive = Some(drive),
+        Err(e) => println!("couldn't create the attachments drive, attachments are disabled: {:?}", e),
+    }
+
     bind_http_path("/get_leaderboard", true, false).unwrap();
+    bind_http_path("/leaderboard", true, false).unwrap();
+    bind_http_path("/get_chat", true, false).unwrap();
+    bind_http_path("/search_chat", true, false).unwrap();
+    bind_http_path("/god_says", true, false).unwrap();
+    bind_http_path("/moderation", true, false).unwrap();
     bind_http_path("/add_respect", true, false).unwrap();
+    bind_http_path("/offerings", true, false).unwrap();
+    bind_http_path("/respect_rules", true, false).unwrap();
+    bind_http_path("/congregations", true, false).unwrap();
+    bind_http_path("/rank_history", true, false).unwrap();
     bind_http_path("/set_discoverable", true, false).unwrap();
     bind_http_path("/remove_leaderboard_entry", true, false).unwrap();
     bind_http_path("/send_contact_request", true, false).unwrap();
     bind_http_path("/accept_contact", true, false).unwrap();
     bind_http_path("/decline_contact", true, false).unwrap();
     bind_http_path("/send_chat_message", true, false).unwrap();
+    bind_http_path("/upload_attachment", true, false).unwrap();
+    bind_http_path("/attachment", true, false).unwrap();
+    bind_http_path("/typing", true, false).unwrap();
+    bind_http_path("/mark_read", true, false).unwrap();
+    bind_http_path("/edit_chat_message", true, false).unwrap();
+    bind_http_path("/delete_chat_message", true, false).unwrap();
+    bind_http_path("/react", true, false).unwrap();
+    bind_http_path("/get_conversations", true, false).unwrap();
+    bind_http_path("/create_room", true, false).unwrap();
+    bind_http_path("/invite_to_room", true, false).unwrap();
+    bind_http_path("/accept_room_invite", true, false).unwrap();
+    bind_http_path("/decline_room_invite", true, false).unwrap();
+    bind_http_path("/leave_room", true, false).unwrap();
+    bind_http_path("/kick_from_room", true, false).unwrap();
 
     // Bind WebSocket path
     bind_ws_path("/", true, false).unwrap();
 
     kinode_process_lib::timer::set_timer(10_000, None);
 
-    while let Ok(message) = await_message() {
-        println!("our state: {:?}", state);
-        handle_message(&our, &mut state, message);
+    loop {
+        match await_message() {
+            Ok(message) => {
+                println!("our state: {:?}", state);
+                handle_message(&our, &mut state, message);
+            },
+            Err(send_error) => handle_send_error(&mut state, &send_error),
+        }
         state.save();
     }
 }
@@ -53,16 +115,44 @@ fn handle_message(our: &Address, state: &mut State, message: Message) {
             "http_server:distro:sys" => handle_http_request(our, state, &message),
             _ => println!("other process than the shrine"),
         }
-    } else if state.discoverable || state.pending_contact_requests.contains(&message.source().node) || state.contacts.contains(&message.source().node){ 
+    } else if state.discoverable || state.pending_contact_requests.contains(&message.source().node) || state.contacts.contains(&message.source().node)
+        || state.is_room_member(&message.source().node) { 
         println!("Incoming alien message");
         handle_alien_message(our, state, &message);
     }
 }
 
+// a contact we sent a chat message to didn't ack it in time, or an attachment owner didn't answer
+fn handle_send_error(state: &mut State, send_error: &SendError) {
+    if let Ok(AttachmentRequest::FetchAttachment { id }) = serde_json::from_slice::<AttachmentRequest>(send_error.message().body()) {
+        let owner = send_error.target().node.clone();
+        println!("{} didn't answer for attachment {}", owner, id);
+        state.attachment_fetches.remove(&(owner.clone(), id.clone()));
+        push_ws_update(state, &WsUpdate::AttachmentFetched { owner, id, found: false });
+        return;
+    }
+    let chat_request = match serde_json::from_slice::<ChatRequest>(send_error.message().body()) {
+        Ok(ChatRequest::Sealed(sealed)) => state.open(&send_error.target().node, &sealed, true).ok(),
+        chat_request => chat_request.ok(),
+    };
+    if let Some(ChatRequest::ChatMessageReceived(chat_message)) = chat_request {
+        if chat_message.sender == state.node_id {
+            println!("couldn't reach {}, queueing chat message for retry", send_error.target().node);
+            state.requeue_chat_message(&chat_message.id, &send_error.target().node);
+        }
+    }
+}
+
 // the timing needs to be more sophisicated 
 fn handle_timer_events(our: &Address, state: &mut State) {
     //println!("timer update.");
+    state.refresh_windows();
     push_update_to_your_contacts(our, state);
+    push_gossip_to_your_contacts(state);
+    push_congregation_standings(state);
+    rotate_chat_keys(state);
+    resend_queued_chat_messages(state);
+    state.prune_typing();
     if !state.pending_contact_requests.is_empty() {
         resend_pending_requests(state);
     }
@@ -84,25 +174,395 @@ fn process_http_request(
     state: &mut State
 ) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
     let server_request = http::HttpServerRequest::from_bytes(body).ok()?;
+    match server_request {
+        http::HttpServerRequest::WebSocketOpen { channel_id, .. } => {
+            state.ws_channels.insert(channel_id);
+            return None;
+        },
+        http::HttpServerRequest::WebSocketClose(channel_id) => {
+            state.ws_channels.remove(&channel_id);
+            return None;
+        },
+        _ => {},
+    }
     let http_request = server_request.request()?;
     let bound_path = http_request.bound_path(Some(&our.process())).rsplit('/').next().unwrap_or("");
 
     match http_request.method().ok()? {
-        http::Method::GET => handle_get_request(bound_path, state),
+        http::Method::GET => handle_get_request(bound_path, state, &http_request),
         http::Method::POST => handle_post_request(bound_path, state, &http_request),
         _ => None,
     }
 }
 
-fn handle_get_request(bound_path: &str, state: &State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+fn handle_get_request(bound_path: &str, state: &mut State, http_request: &http::IncomingHttpRequest) 
+-> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
     match bound_path {
         "get_leaderboard" => {
             let mut headers = HashMap::new();
             headers.insert("Content-Type".to_string(), "application/json".to_string());
-            let body = serde_json::to_vec(state).ok()?;
+            // our private keys stay in the process
+            let mut state_json = serde_json::to_value(state).ok()?;
+            state_json.as_object_mut()?.remove("chat_keys");
+            let body = serde_json::to_vec(&state_json).ok()?;
+            Some((http::StatusCode::OK, headers, body))
+        },
+        "leaderboard" => handle_leaderboard(state, http_request),
+        "get_chat" => handle_get_chat(state, http_request),
+        "search_chat" => handle_search_chat(state, http_request),
+        "attachment" => handle_get_attachment(state, http_request),
+        "moderation" => {
+            let mut headers = HashMap::new();
+            headers.insert("Content-Type".to_string(), "application/json".to_string());
+            let body = serde_json::to_vec(&state.moderation_view()).ok()?;
+            Some((http::StatusCode::OK, headers, body))
+        },
+        "offerings" => {
+            let mut headers = HashMap::new();
+            headers.insert("Content-Type".to_string(), "application/json".to_string());
+            let body = serde_json::to_vec(&state.offering_catalogue).ok()?;
+            Some((http::StatusCode::OK, headers, body))
+        },
+        "rank_history" => {
+            let mut headers = HashMap::new();
+            headers.insert("Content-Type".to_string(), "application/json".to_string());
+            let body = serde_json::to_vec(&state.rank_history_view()).ok()?;
+            Some((http::StatusCode::OK, headers, body))
+        },
+        "congregations" => {
+            let mut headers = HashMap::new();
+            headers.insert("Content-Type".to_string(), "application/json".to_string());
+            match LeaderboardQuery::from_params(http_request.query_params(), &state.offering_catalogue) {
+                Ok(query) => Some((http::StatusCode::OK, headers, serde_json::to_vec(&state.ranked_congregations(&query)).ok()?)),
+                Err(e) => {
+                    let body = serde_json::to_vec(&serde_json::json!({ "error": e })).ok()?;
+                    Some((http::StatusCode::BAD_REQUEST, headers, body))
+                }
+            }
+        },
+        "respect_rules" => {
+            let mut headers = HashMap::new();
+            headers.insert("Content-Type".to_string(), "application/json".to_string());
+            let view = RespectRulesView { rules: &state.respect_rules, allowance: state.respect_allowance(std::time::SystemTime::now()) };
+            Some((http::StatusCode::OK, headers, serde_json::to_vec(&view).ok()?))
+        },
+        "god_says" => {
+            let mut headers = HashMap::new();
+            headers.insert("Content-Type".to_string(), "application/json".to_string());
+            match revelation_from_params(http_request.query_params()) {
+                Ok(revelation) => Some((http::StatusCode::OK, headers, serde_json::to_vec(&revelation).ok()?)),
+                Err(e) => {
+                    let body = serde_json::to_vec(&serde_json::json!({ "error": e })).ok()?;
+                    Some((http::StatusCode::BAD_REQUEST, headers, body))
+                }
+            }
+        },
+        "get_conversations" => {
+            let mut headers = HashMap::new();
+            headers.insert("Content-Type".to_string(), "application/json".to_string());
+            let body = serde_json::to_vec(&state.conversation_summaries()).ok()?;
+            Some((http::StatusCode::OK, headers, body))
+        },
+        _ => None,
+    }
+}
+
+fn handle_leaderboard(state: &mut State, http_request: &http::IncomingHttpRequest) 
+-> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    match LeaderboardQuery::from_params(http_request.query_params(), &state.offering_catalogue) {
+        Ok(query) => {
+            let body = serde_json::to_vec(&state.leaderboard_page(&query)).ok()?;
             Some((http::StatusCode::OK, headers, body))
         },
+        Err(e) => {
+            println!("(LOCAL) bad leaderboard query: {}", e);
+            let body = serde_json::to_vec(&serde_json::json!({ "error": e })).ok()?;
+            Some((http::StatusCode::BAD_REQUEST, headers, body))
+        }
+    }
+}
+
+fn handle_get_chat(state: &State, http_request: &http::IncomingHttpRequest) 
+-> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    match ChatQuery::from_params(http_request.query_params()) {
+        Ok(query) => {
+            let body = serde_json::to_vec(&state.query_chat(&query)).ok()?;
+            Some((http::StatusCode::OK, headers, body))
+        },
+        Err(e) => {
+            println!("(LOCAL) bad get_chat query: {}", e);
+            let body = serde_json::to_vec(&serde_json::json!({ "error": e })).ok()?;
+            Some((http::StatusCode::BAD_REQUEST, headers, body))
+        }
+    }
+}
+
+// /search_chat?q=...&conversation=...&limit=...
+fn handle_search_chat(state: &State, http_request: &http::IncomingHttpRequest) 
+-> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    let params = http_request.query_params();
+    let Some(query) = params.get("q") else {
+        return Some((http::StatusCode::BAD_REQUEST, headers, Vec::new()));
+    };
+    let conversation = match params.get("conversation").map(|value| parse_conversation(value)).transpose() {
+        Ok(conversation) => conversation,
+        Err(e) => {
+            println!("(LOCAL) bad search_chat query: {}", e);
+            return Some((http::StatusCode::BAD_REQUEST, headers, Vec::new()));
+        }
+    };
+    let limit = params.get("limit")
+        .and_then(|limit| limit.parse::<usize>().ok())
+        .map_or(DEFAULT_SEARCH_LIMIT, |limit| limit.clamp(1, MAX_SEARCH_LIMIT));
+    let results = state.search_chat(query, conversation.as_ref(), limit);
+    let body = serde_json::to_vec(&serde_json::json!({ "results": results })).ok()?;
+    Some((http::StatusCode::OK, headers, body))
+}
+
+// /attachment?owner=...&id=... serves the raw file. someone else's file is fetched from its
+// owner the first time, answered with 202 until it arrives, and kept in our drive after that
+fn handle_get_attachment(state: &mut State, http_request: &http::IncomingHttpRequest) 
+-> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let params = http_request.query_params();
+    let (Some(owner), Some(id)) = (params.get("owner"), params.get("id")) else {
+        return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+    };
+    let Some(drive) = &state.attachments_drive else {
+        return Some((http::StatusCode::SERVICE_UNAVAILABLE, HashMap::new(), Vec::new()));
+    };
+    // only files we have metadata for, so the owner and id can't point anywhere else in the drive
+    let Some(attachment) = state.find_attachment(owner, id).cloned() else {
+        return Some((http::StatusCode::NOT_FOUND, HashMap::new(), Vec::new()));
+    };
+    let bytes = match read_attachment(&attachment_path(drive, &attachment.owner, &attachment.id)) {
+        Some(bytes) => bytes,
+        None if attachment.owner != state.node_id => {
+            request_attachment(state, &attachment);
+            return Some((http::StatusCode::ACCEPTED, HashMap::new(), Vec::new()));
+        },
+        None => return Some((http::StatusCode::NOT_FOUND, HashMap::new(), Vec::new())),
+    };
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), attachment.mime.clone());
+    headers.insert("X-Content-Type-Options".to_string(), "nosniff".to_string());
+    headers.insert("Content-Disposition".to_string(), format!("inline; filename=\"{}\"", attachment.name.replace('"', "")));
+    Some((http::StatusCode::OK, headers, bytes))
+}
+
+// asks the owner for the file unless we already are. the id rides along as the context
+fn request_attachment(state: &mut State, attachment: &Attachment) {
+    let fetch = (attachment.owner.clone(), attachment.id.clone());
+    if state.attachment_fetches.contains(&fetch) {
+        return;
+    }
+    let request = AttachmentRequest::FetchAttachment { id: attachment.id.clone() };
+    let Ok(body) = serde_json::to_vec(&request) else { return };
+    match Request::new()
+        .body(body)
+        .target(&shrine_address(&attachment.owner))
+        .context(attachment.id.as_bytes())
+        .expects_response(ATTACHMENT_FETCH_TIMEOUT)
+        .send()
+    {
+        Ok(()) => {
+            state.attachment_fetches.insert(fetch);
+        },
+        Err(e) => println!("Failed to request attachment {} from {}: {:?}", attachment.id, attachment.owner, e),
+    }
+}
+
+// the owner answered a fetch. only keeps what matches the metadata we were sent
+fn handle_attachment_response(state: &mut State, their_node: &NodeId, message: &Message, response: AttachmentResponse) {
+    let Some(id) = message.context().and_then(|context| String::from_utf8(context.to_vec()).ok()) else {
+        return;
+    };
+    if !state.attachment_fetches.remove(&(their_node.clone(), id.clone())) {
+        println!("ignored an attachment {} from {} we didn't ask for", id, their_node);
+        return;
+    }
+    let (Some(attachment), Some(drive)) = (state.find_attachment(their_node, &id), &state.attachments_drive) else {
+        return;
+    };
+    let bytes = match (response, message.blob()) {
+        (AttachmentResponse::AttachmentFound(sealed), Some(blob)) => match state.open_blob(their_node, &id, &sealed, &blob.bytes) {
+            Ok(bytes) if bytes.len() == attachment.size && sniff_mime(&bytes) == Some(attachment.mime.as_str()) => Some(bytes),
+            Ok(_) => {
+                println!("attachment {} from {} doesn't match its metadata", id, their_node);
+                None
+            },
+            Err(e) => {
+                println!("couldn't open attachment {} from {}: {}", id, their_node, e);
+                None
+            },
+        },
         _ => None,
+    };
+    let found = match bytes {
+        Some(bytes) => match write_attachment(&attachment_path(drive, their_node, &id), &bytes) {
+            Ok(()) => true,
+            Err(e) => {
+                println!("couldn't store attachment {} from {}: {}", id, their_node, e);
+                false
+            },
+        },
+        None => false,
+    };
+    push_ws_update(state, &WsUpdate::AttachmentFetched { owner: their_node.clone(), id, found });
+}
+
+// /upload_attachment?name=...&mime=... with the file as the body
+fn handle_upload_attachment(state: &mut State, http_request: &http::IncomingHttpRequest) 
+-> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    let Some(drive) = state.attachments_drive.clone() else {
+        return Some((http::StatusCode::SERVICE_UNAVAILABLE, headers, Vec::new()));
+    };
+    let bytes = get_blob().map(|blob| blob.bytes).unwrap_or_default();
+    if bytes.is_empty() {
+        return Some((http::StatusCode::BAD_REQUEST, headers, Vec::new()));
+    }
+    if bytes.len() > MAX_ATTACHMENT_BYTES {
+        return Some((http::StatusCode::PAYLOAD_TOO_LARGE, headers, Vec::new()));
+    }
+    // the declared type has to agree with what the bytes look like. any text/* is stored as plain text
+    let params = http_request.query_params();
+    let declared = params.get("mime")
+        .map(|mime| mime.split(';').next().unwrap_or_default().trim())
+        .filter(|mime| !mime.is_empty());
+    let mime = match (sniff_mime(&bytes), declared) {
+        (Some("text/plain"), Some(declared)) if declared.starts_with("text/") => Some("text/plain"),
+        (Some(sniffed), Some(declared)) if declared != sniffed => None,
+        (sniffed, _) => sniffed,
+    };
+    let Some(mime) = mime else {
+        println!("(LOCAL) rejected attachment upload of unsupported type");
+        return Some((http::StatusCode::UNSUPPORTED_MEDIA_TYPE, headers, Vec::new()));
+    };
+    let attachment = Attachment {
+        id: state.next_local_id(),
+        owner: state.node_id.clone(),
+        name: sanitize_name(params.get("name").map(String::as_str).unwrap_or_default()),
+        mime: mime.to_string(),
+        size: bytes.len(),
+    };
+    if let Err(e) = write_attachment(&attachment_path(&drive, &attachment.owner, &attachment.id), &bytes) {
+        println!("(LOCAL) couldn't store attachment: {}", e);
+        return Some((http::StatusCode::INTERNAL_SERVER_ERROR, headers, Vec::new()));
+    }
+    let body = serde_json::to_vec(&attachment).ok()?;
+    state.attachments.insert(attachment.id.clone(), attachment);
+    Some((http::StatusCode::CREATED, headers, body))
+}
+
+// mutes and filters, answered with the filter that was added if any
+fn handle_moderation(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    let Some(parsed_body) = parse_request_body::<ModerationBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, headers, Vec::new()));
+    };
+    match state.apply_moderation(parsed_body) {
+        Ok(Some(filter)) => Some((http::StatusCode::CREATED, headers, serde_json::to_vec(&filter).ok()?)),
+        Ok(None) => Some((http::StatusCode::OK, headers, Vec::new())),
+        Err(e) => {
+            println!("(LOCAL) moderation change refused: {}", e);
+            let body = serde_json::to_vec(&serde_json::json!({ "error": e })).ok()?;
+            Some((http::StatusCode::UNPROCESSABLE_ENTITY, headers, body))
+        }
+    }
+}
+
+fn handle_offerings(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    let Some(parsed_body) = parse_request_body::<OfferingBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, headers, Vec::new()));
+    };
+    match state.apply_offering_change(parsed_body) {
+        Ok(Some(offering)) => Some((http::StatusCode::CREATED, headers, serde_json::to_vec(&offering).ok()?)),
+        Ok(None) => Some((http::StatusCode::OK, headers, Vec::new())),
+        Err(e) => {
+            println!("(LOCAL) offering change refused: {}", e);
+            let body = serde_json::to_vec(&serde_json::json!({ "error": e })).ok()?;
+            Some((http::StatusCode::UNPROCESSABLE_ENTITY, headers, body))
+        }
+    }
+}
+
+fn handle_add_respect(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    // older UIs post without a body
+    let has_body = get_blob().map_or(false, |blob| !blob.bytes.is_empty());
+    let parsed_body = if has_body { parse_request_body::<AddRespectBody>() } else { Some(AddRespectBody::default()) };
+    let Some(parsed_body) = parsed_body else {
+        return Some((http::StatusCode::BAD_REQUEST, headers, Vec::new()));
+    };
+    let offering = parsed_body.offering.unwrap_or(DEFAULT_OFFERING.to_string());
+    match state.add_respect(&offering) {
+        Ok(achievements) => {
+            refresh_standings(state);
+            for achievement in achievements {
+                let content = format!("🏆 {} earned \"{}\"", state.node_id, achievement.title());
+                let chat_message = ChatMessage::new(state.next_message_id(), state.node_id.clone(), content, ConversationId::Shrine);
+                publish_chat_message(state, chat_message);
+            }
+            // what's left after this one, so the UI can grey out the button
+            let body = serde_json::to_vec(&state.respect_allowance(std::time::SystemTime::now())).ok()?;
+            Some((http::StatusCode::OK, headers, body))
+        },
+        Err(e) => {
+            println!("(LOCAL) respect refused: {}", e);
+            let status = match e {
+                RespectRefused::UnknownOffering(_) => http::StatusCode::BAD_REQUEST,
+                RespectRefused::Cooldown | RespectRefused::DailyCap => http::StatusCode::TOO_MANY_REQUESTS,
+            };
+            let allowance = state.respect_allowance(std::time::SystemTime::now());
+            headers.insert("Retry-After".to_string(), allowance.retry_after_secs.to_string());
+            let body = serde_json::to_vec(&serde_json::json!({ "error": e.to_string(), "allowance": allowance })).ok()?;
+            Some((status, headers, body))
+        }
+    }
+}
+
+fn handle_respect_rules(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    let Some(parsed_body) = parse_request_body::<RespectRules>() else {
+        return Some((http::StatusCode::BAD_REQUEST, headers, Vec::new()));
+    };
+    if let Err(e) = state.set_respect_rules(parsed_body) {
+        println!("(LOCAL) respect rules refused: {}", e);
+        let body = serde_json::to_vec(&serde_json::json!({ "error": e })).ok()?;
+        return Some((http::StatusCode::UNPROCESSABLE_ENTITY, headers, body));
+    }
+    let view = RespectRulesView { rules: &state.respect_rules, allowance: state.respect_allowance(std::time::SystemTime::now()) };
+    Some((http::StatusCode::OK, headers, serde_json::to_vec(&view).ok()?))
+}
+
+fn handle_remove_leaderboard_entry(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    let Some(parsed_body) = parse_request_body::<RemoveEntryBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, headers, Vec::new()));
+    };
+    match state.remove_entry(&parsed_body.node, parsed_body.mode) {
+        Ok(()) => {
+            refresh_standings(state);
+            Some((http::StatusCode::OK, HashMap::new(), Vec::new()))
+        },
+        Err(e) => {
+            println!("(LOCAL) couldn't remove leaderboard entry: {}", e);
+            let body = serde_json::to_vec(&serde_json::json!({ "error": e })).ok()?;
+            Some((http::StatusCode::UNPROCESSABLE_ENTITY, headers, body))
+        }
     }
 }
 
@@ -110,10 +570,13 @@ fn handle_get_request(bound_path: &str, state: &State) -> Option<(http::StatusCo
 fn handle_post_request(bound_path: &str, state: &mut State, http_request: &http::IncomingHttpRequest) 
 -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
     match bound_path {
-        "add_respect" => {
-            state.add_respect();
-            Some((http::StatusCode::OK, HashMap::new(), Vec::new()))
-        },
+        "upload_attachment" => handle_upload_attachment(state, http_request),
+        "moderation" => handle_moderation(state),
+        "offerings" => handle_offerings(state),
+        "add_respect" => handle_add_respect(state),
+        "respect_rules" => handle_respect_rules(state),
+        "congregations" => handle_congregations(state),
+        "remove_leaderboard_entry" => handle_remove_leaderboard_entry(state),
         "send_contact_request" => handle_send_contact_request(state, http_request),
         "set_discoverable" => {
             state.set_discoverable(!state.discoverable);
@@ -122,6 +585,17 @@ fn handle_post_request(bound_path: &str, state: &mut State, http_request: &http:
         "accept_contact" => handle_accept_contact(state, http_request),
         "decline_contact" => handle_decline_contact(state, http_request),
         "send_chat_message" => handle_send_chat_message(state, http_request),
+        "typing" => handle_typing(state),
+        "mark_read" => handle_mark_read(state),
+        "edit_chat_message" => handle_edit_chat_message(state),
+        "delete_chat_message" => handle_delete_chat_message(state),
+        "react" => handle_react(state),
+        "create_room" => handle_create_room(state),
+        "invite_to_room" => handle_invite_to_room(state),
+        "accept_room_invite" => handle_accept_room_invite(state),
+        "decline_room_invite" => handle_decline_room_invite(state),
+        "leave_room" => handle_leave_room(state),
+        "kick_from_room" => handle_kick_from_room(state),
         _ => None,
     }
 }
@@ -143,6 +617,7 @@ fn handle_send_contact_request(state: &mut State, http_request: &http::IncomingH
                 .body(serde_json::to_vec(&ContactRequest::RequestContact(parsed_body.node.clone())).ok()?)
                 .target(&their_addy)
                 .send().ok()?;
+            send_key_exchange(state, &parsed_body.node);
             state.append_outgoing_contact_request(parsed_body.node);
             Some((http::StatusCode::OK, headers, Vec::new()))
         },
@@ -174,6 +649,9 @@ fn handle_accept_contact(state: &mut State, http_request: &http::IncomingHttpReq
                 .target(&their_addy)
                 .send().ok()?;
             println!("sent contact accepted to {:?}", &their_node.to_string());
+            // before the history request, so their backfill can already come back sealed
+            send_key_exchange(state, &their_node);
+            request_history(state, &their_node);
             Some((http::StatusCode::OK, headers, Vec::new()))
         },
         Err(e) => {
@@ -215,30 +693,56 @@ fn handle_send_chat_message(
 
     match serde_json::from_str::<ChatMessageBody>(body_str) {
         Ok(parsed_body) => {
-            let chat_message = ChatMessage {
-                sender: state.node_id.clone(),
-                content: parsed_body.content.clone(),
-                timestamp: std::time::SystemTime::now(), 
-            };
-            state.add_chat_message(chat_message.clone());
-
-            let chat_message = ChatRequest::ChatMessageReceived(chat_message.clone());
-            // us.send(message) -> their handler, which should somehow be picked up by the websocket match statement.
-            match serde_json::to_vec(&chat_message) {
-                Ok(serialized_message) => {
-                    for contact in &state.contacts {
-                        let their_addy = Address {
-                            node: contact.clone(),
-                            process: ProcessId::from_str("updated_shrine:td_shrine:sharmouta.os").ok().unwrap(),
-                        };
-                        Request::new()
-                            .body(serialized_message.clone())
-                            .target(&their_addy)
-                            .send().ok().unwrap();
+            if parsed_body.attachments.len() > MAX_ATTACHMENTS_PER_MESSAGE {
+                return Some((http::StatusCode::BAD_REQUEST, headers, Vec::new()));
+            }
+            let mut attachments = Vec::new();
+            for id in &parsed_body.attachments {
+                match state.attachments.get(id) {
+                    Some(attachment) => attachments.push(attachment.clone()),
+                    None => {
+                        println!("(LOCAL) no uploaded attachment with id {}", id);
+                        return Some((http::StatusCode::BAD_REQUEST, headers, Vec::new()));
                     }
                 }
-                Err(_e) => println!("Failed to serialize chat message: {:?}", chat_message)
             }
+            // an attachment can go out without any text
+            let content = match sanitize_content(&parsed_body.content) {
+                Ok(content) => content,
+                Err(ContentError::Empty) if !attachments.is_empty() => String::new(),
+                Err(e) => return content_error_response(e),
+            };
+            // `/god` goes out as the oracle's answer, so everyone in the conversation hears it
+            let content = match god_command(&content) {
+                Some(revelation) => revelation.to_chat(),
+                None => content,
+            };
+            match &parsed_body.conversation {
+                ConversationId::Direct(node) if state.recipients(&parsed_body.conversation).is_empty() => {
+                    println!("(LOCAL) {} isn't one of your contacts", node);
+                    return Some((http::StatusCode::FORBIDDEN, headers, Vec::new()));
+                },
+                ConversationId::Room(room_id) => match state.rooms.get(room_id) {
+                    None => {
+                        println!("(LOCAL) no room {}", room_id);
+                        return Some((http::StatusCode::NOT_FOUND, headers, Vec::new()));
+                    },
+                    Some(room) if !room.members.contains(&state.node_id) => {
+                        println!("(LOCAL) you're not a member of {}", room_id);
+                        return Some((http::StatusCode::FORBIDDEN, headers, Vec::new()));
+                    },
+                    Some(_) => {},
+                },
+                _ => {},
+            }
+            let mut chat_message = ChatMessage::new(
+                state.next_message_id(),
+                state.node_id.clone(),
+                content,
+                parsed_body.conversation,
+            );
+            chat_message.attachments = attachments;
+            publish_chat_message(state, chat_message);
             Some((http::StatusCode::OK, headers, Vec::new()))
         },
         Err(e) => {
@@ -248,6 +752,456 @@ fn handle_send_chat_message(
     }
 }
 
+// sends a new message of ours to everyone in its conversation and files it
+fn publish_chat_message(state: &mut State, mut chat_message: ChatMessage) {
+    for contact in state.recipients(&chat_message.conversation) {
+        let status = deliver_chat_message(state, &contact, &chat_message);
+        chat_message.receipts.insert(contact, status);
+    }
+    state.add_chat_message(chat_message);
+}
+
+// sends one of our chat messages to a contact, who acks it with a Delivered receipt.
+// it stays Queued until we share a key with them
+fn deliver_chat_message(state: &mut State, contact: &NodeId, chat_message: &ChatMessage) -> DeliveryStatus {
+    let mut outgoing = chat_message.clone();
+    outgoing.receipts.clear();
+    outgoing.moderation = None;
+    let their_addy = shrine_address(contact);
+    let Some(body) = chat_request_body(state, contact, &ChatRequest::ChatMessageReceived(outgoing)) else {
+        return DeliveryStatus::Queued;
+    };
+    match Request::new().body(body).target(&their_addy).expects_response(DELIVERY_TIMEOUT).send() {
+        Ok(()) => DeliveryStatus::Sent,
+        Err(e) => {
+            println!("Failed to send chat message to {}: {:?}", contact, e);
+            DeliveryStatus::Queued
+        }
+    }
+}
+
+// retries every recipient that hasn't acked one of our messages yet
+fn resend_queued_chat_messages(state: &mut State) {
+    // room members needn't be contacts, so go by who's still in the message's conversation
+    let queued: Vec<(ChatMessage, NodeId)> = state.all_chat_messages()
+        .filter(|message| message.sender == state.node_id)
+        .flat_map(|message| {
+            let recipients = state.recipients(&message.conversation);
+            message.receipts.iter()
+                .filter(move |(contact, status)| **status == DeliveryStatus::Queued && recipients.contains(contact))
+                .map(move |(contact, _)| (message.clone(), contact.clone()))
+        })
+        .collect();
+    for (chat_message, contact) in queued {
+        let status = deliver_chat_message(state, &contact, &chat_message);
+        if let Some(chat_message) = state.find_chat_message_mut(&chat_message.id) {
+            chat_message.receipts.insert(contact, status);
+        }
+    }
+}
+
+// the UI has shown a conversation, let the senders know. no body means the shrine room
+fn handle_mark_read(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let parsed_body = match get_blob() {
+        Some(body) if !body.bytes.is_empty() => match serde_json::from_slice::<MarkReadBody>(&body.bytes) {
+            Ok(parsed_body) => parsed_body,
+            Err(e) => {
+                println!("(LOCAL) failed to parse mark_read body: {:?}", e);
+                return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+            }
+        },
+        _ => MarkReadBody::default(),
+    };
+    for (sender, id) in state.mark_chat_read(&parsed_body.conversation) {
+        send_chat_request(state, &sender, &ChatRequest::Receipt { id, status: DeliveryStatus::Read });
+    }
+    Some((http::StatusCode::OK, HashMap::new(), Vec::new()))
+}
+
+// tells the conversation we're typing, the UI is expected to call this on keystrokes. no body means the shrine room
+fn handle_typing(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let parsed_body = match get_blob() {
+        Some(body) if !body.bytes.is_empty() => match serde_json::from_slice::<TypingBody>(&body.bytes) {
+            Ok(parsed_body) => parsed_body,
+            Err(e) => {
+                println!("(LOCAL) failed to parse typing body: {:?}", e);
+                return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+            }
+        },
+        _ => TypingBody::default(),
+    };
+    if !state.try_mark_typing(&parsed_body.conversation) {
+        return Some((http::StatusCode::TOO_MANY_REQUESTS, HashMap::new(), Vec::new()));
+    }
+    let typing = ChatRequest::Typing { conversation: parsed_body.conversation.clone() };
+    for recipient in state.recipients(&parsed_body.conversation) {
+        send_chat_request(state, &recipient, &typing);
+    }
+    Some((http::StatusCode::OK, HashMap::new(), Vec::new()))
+}
+
+fn handle_edit_chat_message(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let Some(parsed_body) = parse_request_body::<EditChatMessageBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+    };
+    let content = match sanitize_content(&parsed_body.content) {
+        Ok(content) => content,
+        Err(e) => return content_error_response(e),
+    };
+    let edited_at = std::time::SystemTime::now();
+    let node_id = state.node_id.clone();
+    let Some((conversation, _)) = state.apply_edit(&parsed_body.id, &node_id, content.clone(), edited_at) else {
+        return Some((http::StatusCode::NOT_FOUND, HashMap::new(), Vec::new()));
+    };
+    let edit = ChatRequest::EditMessage { id: parsed_body.id.clone(), content: content.clone(), edited_at };
+    for recipient in state.recipients(&conversation) {
+        send_chat_request(state, &recipient, &edit);
+    }
+    push_ws_update(state, &WsUpdate::MessageEdited { id: parsed_body.id, content, edited_at });
+    Some((http::StatusCode::OK, HashMap::new(), Vec::new()))
+}
+
+fn handle_delete_chat_message(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let Some(parsed_body) = parse_request_body::<DeleteChatMessageBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+    };
+    let node_id = state.node_id.clone();
+    let Some(conversation) = state.apply_delete(&parsed_body.id, &node_id) else {
+        return Some((http::StatusCode::NOT_FOUND, HashMap::new(), Vec::new()));
+    };
+    let tombstone = ChatRequest::DeleteMessage { id: parsed_body.id.clone() };
+    for recipient in state.recipients(&conversation) {
+        send_chat_request(state, &recipient, &tombstone);
+    }
+    push_ws_update(state, &WsUpdate::MessageDeleted { id: parsed_body.id });
+    Some((http::StatusCode::OK, HashMap::new(), Vec::new()))
+}
+
+fn handle_react(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let Some(parsed_body) = parse_request_body::<ReactionBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+    };
+    let node_id = state.node_id.clone();
+    let add = !parsed_body.remove;
+    let Some(conversation) = state.apply_reaction(&parsed_body.id, &node_id, &parsed_body.emoji, add) else {
+        // nothing changed, either already reacted or not a valid message/emoji
+        return Some((http::StatusCode::OK, HashMap::new(), Vec::new()));
+    };
+    let reaction = if add {
+        ChatRequest::AddReaction { id: parsed_body.id.clone(), emoji: parsed_body.emoji.clone() }
+    } else {
+        ChatRequest::RemoveReaction { id: parsed_body.id.clone(), emoji: parsed_body.emoji.clone() }
+    };
+    for recipient in state.recipients(&conversation) {
+        send_chat_request(state, &recipient, &reaction);
+    }
+    push_ws_update(state, &WsUpdate::Reaction { id: parsed_body.id, emoji: parsed_body.emoji, node: node_id, added: add });
+    Some((http::StatusCode::OK, HashMap::new(), Vec::new()))
+}
+
+fn handle_create_room(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let Some(parsed_body) = parse_request_body::<CreateRoomBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+    };
+    let name = parsed_body.name.trim().to_string();
+    if name.is_empty() {
+        return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+    }
+    match state.create_room(name) {
+        Some(room_id) => {
+            let mut headers = HashMap::new();
+            headers.insert("Content-Type".to_string(), "application/json".to_string());
+            let body = serde_json::to_vec(&serde_json::json!({ "room_id": room_id })).ok()?;
+            Some((http::StatusCode::OK, headers, body))
+        },
+        None => Some((http::StatusCode::CONFLICT, HashMap::new(), Vec::new())),
+    }
+}
+
+// only the owner invites, and only their own contacts
+fn handle_invite_to_room(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let Some(parsed_body) = parse_request_body::<RoomMemberBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+    };
+    if !state.contacts.contains(&parsed_body.node) {
+        return Some((http::StatusCode::FORBIDDEN, HashMap::new(), Vec::new()));
+    }
+    let Some(room) = state.owned_room_mut(&parsed_body.room_id) else {
+        return Some((http::StatusCode::NOT_FOUND, HashMap::new(), Vec::new()));
+    };
+    if room.members.contains(&parsed_body.node) {
+        return Some((http::StatusCode::OK, HashMap::new(), Vec::new()));
+    }
+    if !room.invited.contains(&parsed_body.node) {
+        room.invited.push(parsed_body.node.clone());
+    }
+    let invite = RoomRequest::RoomInvite {
+        room_id: parsed_body.room_id,
+        name: room.name.clone(),
+        members: room.members.clone(),
+    };
+    send_to_shrine(&parsed_body.node, &invite);
+    Some((http::StatusCode::OK, HashMap::new(), Vec::new()))
+}
+
+fn handle_accept_room_invite(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let Some(parsed_body) = parse_request_body::<RoomBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+    };
+    match state.accept_room_invite(&parsed_body.room_id) {
+        Some(owner) => {
+            send_to_shrine(&owner, &RoomRequest::RoomJoin { room_id: parsed_body.room_id });
+            Some((http::StatusCode::OK, HashMap::new(), Vec::new()))
+        },
+        None => Some((http::StatusCode::NOT_FOUND, HashMap::new(), Vec::new())),
+    }
+}
+
+fn handle_decline_room_invite(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let Some(parsed_body) = parse_request_body::<RoomBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+    };
+    match state.room_invites.remove(&parsed_body.room_id) {
+        Some(_) => Some((http::StatusCode::OK, HashMap::new(), Vec::new())),
+        None => Some((http::StatusCode::NOT_FOUND, HashMap::new(), Vec::new())),
+    }
+}
+
+// the owner leaving closes the room for everyone
+fn handle_leave_room(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let Some(parsed_body) = parse_request_body::<RoomBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+    };
+    let Some(room) = state.remove_room(&parsed_body.room_id) else {
+        return Some((http::StatusCode::NOT_FOUND, HashMap::new(), Vec::new()));
+    };
+    if room.owner == state.node_id {
+        let closed = RoomRequest::RoomClosed { room_id: parsed_body.room_id };
+        for node in room.members.iter().chain(room.invited.iter()).filter(|node| **node != state.node_id) {
+            send_to_shrine(node, &closed);
+        }
+    } else {
+        send_to_shrine(&room.owner, &RoomRequest::RoomLeave { room_id: parsed_body.room_id });
+    }
+    Some((http::StatusCode::OK, HashMap::new(), Vec::new()))
+}
+
+fn handle_kick_from_room(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let Some(parsed_body) = parse_request_body::<RoomMemberBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+    };
+    let Some(room) = state.owned_room_mut(&parsed_body.room_id) else {
+        return Some((http::StatusCode::NOT_FOUND, HashMap::new(), Vec::new()));
+    };
+    if parsed_body.node == room.owner || !room.members.contains(&parsed_body.node) {
+        return Some((http::StatusCode::BAD_REQUEST, HashMap::new(), Vec::new()));
+    }
+    room.members.retain(|member| *member != parsed_body.node);
+    send_to_shrine(&parsed_body.node, &RoomRequest::RoomKick { room_id: parsed_body.room_id.clone() });
+    broadcast_room_membership(state, &parsed_body.room_id, RoomEvent::Kicked(parsed_body.node));
+    Some((http::StatusCode::OK, HashMap::new(), Vec::new()))
+}
+
+fn handle_congregations(state: &mut State) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    let Some(parsed_body) = parse_request_body::<CongregationBody>() else {
+        return Some((http::StatusCode::BAD_REQUEST, headers, Vec::new()));
+    };
+    match parsed_body {
+        CongregationBody::Create { name } => match state.create_congregation(&name) {
+            Ok(congregation_id) => {
+                let body = serde_json::to_vec(&serde_json::json!({ "congregation_id": congregation_id })).ok()?;
+                Some((http::StatusCode::CREATED, headers, body))
+            },
+            Err(e) => {
+                let body = serde_json::to_vec(&serde_json::json!({ "error": e })).ok()?;
+                Some((http::StatusCode::UNPROCESSABLE_ENTITY, headers, body))
+            }
+        },
+        // only the founder invites, and only their own contacts
+        CongregationBody::Invite { congregation_id, node } => {
+            if !state.contacts.contains(&node) {
+                return Some((http::StatusCode::FORBIDDEN, headers, Vec::new()));
+            }
+            let Some(congregation) = state.founded_congregation_mut(&congregation_id) else {
+                return Some((http::StatusCode::NOT_FOUND, headers, Vec::new()));
+            };
+            if congregation.members.contains(&node) {
+                return Some((http::StatusCode::OK, headers, Vec::new()));
+            }
+            if congregation.members.len() + congregation.invited.len() >= MAX_CONGREGATION_MEMBERS {
+                return Some((http::StatusCode::CONFLICT, headers, Vec::new()));
+            }
+            if !congregation.invited.contains(&node) {
+                congregation.invited.push(node.clone());
+            }
+            let invite = CongregationRequest::CongregationInvite {
+                congregation_id,
+                name: congregation.name.clone(),
+                members: congregation.members.clone(),
+            };
+            send_to_shrine(&node, &invite);
+            Some((http::StatusCode::OK, headers, Vec::new()))
+        },
+        CongregationBody::Accept { congregation_id } => match state.accept_congregation_invite(&congregation_id) {
+            Some(founder) => {
+                send_to_shrine(&founder, &CongregationRequest::CongregationJoin { congregation_id });
+                Some((http::StatusCode::OK, headers, Vec::new()))
+            },
+            None => Some((http::StatusCode::NOT_FOUND, headers, Vec::new())),
+        },
+        CongregationBody::Decline { congregation_id } => match state.congregation_invites.remove(&congregation_id) {
+            Some(_) => Some((http::StatusCode::OK, headers, Vec::new())),
+            None => Some((http::StatusCode::NOT_FOUND, headers, Vec::new())),
+        },
+        CongregationBody::Leave { congregation_id } => {
+            let Some(congregation) = state.congregations.remove(&congregation_id) else {
+                return Some((http::StatusCode::NOT_FOUND, headers, Vec::new()));
+            };
+            if congregation.founder == state.node_id {
+                let disbanded = CongregationRequest::CongregationDisbanded { congregation_id };
+                for node in congregation.members.iter().chain(congregation.invited.iter()).filter(|node| **node != state.node_id) {
+                    send_to_shrine(node, &disbanded);
+                }
+            } else {
+                send_to_shrine(&congregation.founder, &CongregationRequest::CongregationLeave { congregation_id });
+            }
+            Some((http::StatusCode::OK, headers, Vec::new()))
+        },
+        CongregationBody::Remove { congregation_id, node } => {
+            let Some(congregation) = state.founded_congregation_mut(&congregation_id) else {
+                return Some((http::StatusCode::NOT_FOUND, headers, Vec::new()));
+            };
+            if node == congregation.founder || !(congregation.members.contains(&node) || congregation.invited.contains(&node)) {
+                return Some((http::StatusCode::BAD_REQUEST, headers, Vec::new()));
+            }
+            congregation.members.retain(|member| *member != node);
+            congregation.invited.retain(|invited| *invited != node);
+            send_to_shrine(&node, &CongregationRequest::CongregationRemoved { congregation_id: congregation_id.clone() });
+            broadcast_congregation_membership(state, &congregation_id);
+            Some((http::StatusCode::OK, headers, Vec::new()))
+        },
+    }
+}
+
+// the founder tells every member who's in, along with the entries it can vouch for
+fn broadcast_congregation_membership(state: &State, congregation_id: &str) {
+    let Some(congregation) = state.congregations.get(congregation_id) else { return };
+    let membership = CongregationRequest::CongregationMembership {
+        congregation_id: congregation_id.to_string(),
+        members: congregation.members.clone(),
+        entries: state.congregation_entries(congregation),
+    };
+    for member in congregation.members.iter().filter(|member| **member != state.node_id) {
+        send_to_shrine(member, &membership);
+    }
+    push_ws_update(state, &WsUpdate::CongregationMembership {
+        congregation_id: congregation_id.to_string(),
+        members: congregation.members.clone(),
+    });
+}
+
+// members who aren't each other's contacts only see each other's scores through the founder
+fn push_congregation_standings(state: &State) {
+    let founded: Vec<String> = state.congregations.iter()
+        .filter(|(_, congregation)| congregation.founder == state.node_id && congregation.members.len() > 1)
+        .map(|(congregation_id, _)| congregation_id.clone())
+        .collect();
+    for congregation_id in founded {
+        broadcast_congregation_membership(state, &congregation_id);
+    }
+}
+
+fn handle_congregation_request(state: &mut State, their_node: &NodeId, congregation_request: CongregationRequest) {
+    match congregation_request {
+        CongregationRequest::CongregationInvite { congregation_id, name, members } => {
+            if state.contacts.contains(their_node) && !state.congregations.contains_key(&congregation_id) {
+                let invite = CongregationInvite { name: name.clone(), founder: their_node.clone(), members };
+                state.congregation_invites.insert(congregation_id.clone(), invite);
+                push_ws_update(state, &WsUpdate::CongregationInvite { congregation_id, name, founder: their_node.clone() });
+            }
+        },
+        CongregationRequest::CongregationJoin { congregation_id } => {
+            match state.founded_congregation_mut(&congregation_id) {
+                Some(congregation) if congregation.invited.contains(their_node) => {
+                    congregation.invited.retain(|invited| invited != their_node);
+                    congregation.members.push(their_node.clone());
+                },
+                _ => {
+                    println!("{} tried to join {} without an invite", their_node, congregation_id);
+                    return;
+                }
+            }
+            broadcast_congregation_membership(state, &congregation_id);
+        },
+        CongregationRequest::CongregationLeave { congregation_id } => {
+            match state.founded_congregation_mut(&congregation_id) {
+                Some(congregation) if congregation.members.contains(their_node) => {
+                    congregation.members.retain(|member| member != their_node);
+                },
+                _ => return,
+            }
+            broadcast_congregation_membership(state, &congregation_id);
+        },
+        CongregationRequest::CongregationMembership { congregation_id, members, entries } => {
+            match state.congregations.get_mut(&congregation_id) {
+                Some(congregation) if &congregation.founder == their_node => {
+                    congregation.entries = entries.into_iter()
+                        .filter(|(member, _)| members.contains(member))
+                        .collect();
+                    congregation.members = members.clone();
+                },
+                _ => return,
+            }
+            push_ws_update(state, &WsUpdate::CongregationMembership { congregation_id, members });
+        },
+        CongregationRequest::CongregationRemoved { congregation_id } | CongregationRequest::CongregationDisbanded { congregation_id } => {
+            if state.congregation_invites.get(&congregation_id).is_some_and(|invite| &invite.founder == their_node) {
+                state.congregation_invites.remove(&congregation_id);
+            }
+            if state.congregations.get(&congregation_id).is_some_and(|congregation| &congregation.founder == their_node) {
+                state.congregations.remove(&congregation_id);
+                push_ws_update(state, &WsUpdate::CongregationGone { congregation_id });
+            }
+        },
+    }
+}
+
+// the owner tells every member about the new member list
+fn broadcast_room_membership(state: &State, room_id: &str, event: RoomEvent) {
+    let Some(room) = state.rooms.get(room_id) else { return };
+    let membership = RoomRequest::RoomMembership {
+        room_id: room_id.to_string(),
+        members: room.members.clone(),
+        event: event.clone(),
+    };
+    for member in room.members.iter().filter(|member| **member != state.node_id) {
+        send_to_shrine(member, &membership);
+    }
+    push_ws_update(state, &WsUpdate::RoomEvent { room_id: room_id.to_string(), event });
+}
+
+fn content_error_response(error: ContentError) -> Option<(http::StatusCode, HashMap<String, String>, Vec<u8>)> {
+    println!("(LOCAL) rejected chat content: {}", error);
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    let body = serde_json::to_vec(&serde_json::json!({ "error": error.to_string() })).ok()?;
+    Some((http::StatusCode::BAD_REQUEST, headers, body))
+}
+
+// parses the json body of the current http request
+fn parse_request_body<T: DeserializeOwned>() -> Option<T> {
+    let body = get_blob()?;
+    match serde_json::from_slice::<T>(&body.bytes) {
+        Ok(parsed_body) => Some(parsed_body),
+        Err(e) => {
+            println!("(LOCAL) failed to parse request body: {:?}", e);
+            None
+        }
+    }
+}
+
 fn send_http_response(response: (http::StatusCode, HashMap<String, String>, Vec<u8>)) {
     let (status, headers, body) = response;
     http::send_response(status, Some(headers), body);
@@ -270,34 +1224,380 @@ fn handle_alien_message(our: &Address, state: &mut State, message: &Message) {
                 // pressing accept in the UI triggers that the sender receives this ACK from the originial receiver
                 state.contacts.push(their_node.to_string());
                 println!("{} accepted your request. You are now frens <3", &their_node);
+                request_history(state, their_node);
+            },
+            ContactRequest::KeyExchange { public_key, known_key } => {
+                if !state.may_exchange_keys(their_node) {
+                    println!("ignored a key from {}, who isn't a contact", &their_node);
+                    return;
+                }
+                match state.receive_public_key(&their_node, &public_key, known_key.as_deref()) {
+                    // a request we haven't accepted yet gets our key with the acceptance
+                    Ok(true) if state.contacts.contains(&their_node) || state.is_room_member(&their_node) => send_key_exchange(state, &their_node),
+                    Ok(_) => {},
+                    Err(e) => println!("bad key from {}: {}", &their_node, e),
+                }
             },
             ContactRequest::ContactUpdate(entry) => { 
                 //if they're in our contacts, update their score
-                if state.contacts.contains(&their_node) {
+                if !state.contacts.contains(&their_node) {
+                    println!("request from non-contact (delete this later)");
+                    return;
+                }
+                // the first update after a silence means one of us was offline, so catch up
+                if state.heard_from_contact(their_node) {
+                    request_history(state, their_node);
+                }
+                if state.deleted_entries.contains(their_node) {
+                    println!("ignoring update from {:?}, their entry was deleted", &their_node);
+                } else {
                     state.stats.insert(their_node.to_string(),entry);
+                    refresh_standings(state);
                     println!("updated {:?}", &their_node);
-                } else  {
-                    println!("request from non-contact (delete this later)");
+                }
+            },
+            ContactRequest::LeaderboardGossip(entries) => {
+                if state.contacts.contains(&their_node) {
+                    let merged = state.merge_gossip(their_node, entries);
+                    println!("merged {} gossiped entries from {:?}", merged, &their_node);
                 }
             },
             _ => println!("contact request didn't match anything"),
         }
     } else if let Ok(inc_chat_message) = serde_json::from_slice::<ChatRequest>(message.body()) {
         println!("chat message request in handling");
+        let their_node = message.source().node.clone();
+        let inc_chat_message = match inc_chat_message {
+            ChatRequest::Sealed(sealed) => match state.open(&their_node, &sealed, false) {
+                Ok(ChatRequest::Sealed(_)) => {
+                    println!("dropped a doubly sealed request from {}", their_node);
+                    return;
+                }
+                Ok(chat_request) => chat_request,
+                Err(e) => {
+                    // most likely we lost or never got their current key. no ack, so they'll retry once we've caught up.
+                    // strangers get nothing, or anyone could make us keep keys for them
+                    println!("couldn't open a sealed request from {}: {}", their_node, e);
+                    if state.may_exchange_keys(&their_node) {
+                        send_key_exchange(state, &their_node);
+                    }
+                    return;
+                }
+            },
+            chat_request if chat_request.carries_content() && state.has_session(&their_node) => {
+                println!("refused plaintext chat content from {}, we share a key", their_node);
+                return;
+            }
+            chat_request => chat_request,
+        };
         match inc_chat_message {
-            ChatRequest::ChatMessageReceived(chat_message) => {
+            ChatRequest::ChatMessageReceived(mut chat_message) => {
                 println!("alien chat message = {:?}", chat_message);
-                state.add_chat_message(chat_message); 
+                // refusals are acked too, or a sender with looser checks would retry forever
+                let id = chat_message.id.clone();
+                if let Err(e) = sanitize_remote_message(&mut chat_message) {
+                    println!("rejected chat message from {}: {}", message.source().node, e);
+                    state.record_rejection(e);
+                    ack_chat_message(message, &id, DeliveryStatus::Rejected);
+                    return;
+                }
+                // otherwise anyone could claim someone else's ids and have the real message deduped away
+                if chat_message.sender != message.source().node {
+                    println!("dropped a chat message claiming to be from {}", chat_message.sender);
+                    ack_chat_message(message, &id, DeliveryStatus::Rejected);
+                    return;
+                }
+                let is_new = !state.has_seen_message(&chat_message.id);
+                // a muted or filtered mention doesn't get to ping us
+                let mentions_us = chat_message.mentions.contains(&state.node_id)
+                    && state.moderate(&chat_message.sender, &chat_message.content).is_none();
+                let mention = WsUpdate::Mention {
+                    id: chat_message.id.clone(),
+                    sender: chat_message.sender.clone(),
+                    // a direct message arrives addressed to us but is filed under its sender
+                    conversation: match &chat_message.conversation {
+                        ConversationId::Direct(_) => ConversationId::Direct(chat_message.sender.clone()),
+                        conversation => conversation.clone(),
+                    },
+                    content: chat_message.content.clone(),
+                };
+                if !state.receive_chat_message(chat_message) {
+                    println!("dropped a chat message that wasn't meant for us");
+                    ack_chat_message(message, &id, DeliveryStatus::Rejected);
+                    return;
+                }
+                if is_new && mentions_us {
+                    push_ws_update(state, &mention);
+                }
+                // ack even if it was a duplicate, the first ack might have been lost
+                ack_chat_message(message, &id, DeliveryStatus::Delivered);
+            }
+            ChatRequest::Receipt { id, status } => {
+                let their_node = message.source().node.clone();
+                if state.update_receipt(&id, &their_node, status) {
+                    push_ws_update(state, &WsUpdate::Receipt { id, recipient: their_node, status });
+                }
+            }
+            ChatRequest::EditMessage { id, content, edited_at } => {
+                let content = match sanitize_content(&content) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        println!("rejected edit from {}: {}", message.source().node, e);
+                        state.record_rejection(e);
+                        return;
+                    }
+                };
+                let their_node = message.source().node.clone();
+                if let Some((conversation, mentions_us)) = state.apply_edit(&id, &their_node, content.clone(), edited_at) {
+                    // an edit that adds us pings like a new message would
+                    if mentions_us {
+                        push_ws_update(state, &WsUpdate::Mention { id: id.clone(), sender: their_node, conversation, content: content.clone() });
+                    }
+                    push_ws_update(state, &WsUpdate::MessageEdited { id, content, edited_at });
+                }
+            }
+            ChatRequest::DeleteMessage { id } => {
+                if state.apply_delete(&id, &message.source().node).is_some() {
+                    push_ws_update(state, &WsUpdate::MessageDeleted { id });
+                }
+            }
+            ChatRequest::HistoryRequest { since } => {
+                let their_node = message.source().node.clone();
+                if state.contacts.contains(&their_node) {
+                    let history = state.history_for(&their_node, since);
+                    send_chat_request(state, &their_node, &ChatRequest::HistoryResponse(history));
+                }
+            }
+            ChatRequest::HistoryResponse(messages) => {
+                let their_node = message.source().node.clone();
+                if state.contacts.contains(&their_node) {
+                    let added = state.merge_history(&their_node, messages);
+                    state.history_synced_at.insert(their_node.clone(), std::time::SystemTime::now());
+                    println!("backfilled {} chat messages from {}", added, their_node);
+                    if added > 0 {
+                        push_ws_update(state, &WsUpdate::HistorySynced { node: their_node, added });
+                    }
+                }
+            }
+            ChatRequest::AddReaction { id, emoji } => {
+                let their_node = message.source().node.clone();
+                if state.shares_message(&id, &their_node) && state.apply_reaction(&id, &their_node, &emoji, true).is_some() {
+                    push_ws_update(state, &WsUpdate::Reaction { id, emoji, node: their_node, added: true });
+                }
+            }
+            ChatRequest::RemoveReaction { id, emoji } => {
+                let their_node = message.source().node.clone();
+                if state.shares_message(&id, &their_node) && state.apply_reaction(&id, &their_node, &emoji, false).is_some() {
+                    push_ws_update(state, &WsUpdate::Reaction { id, emoji, node: their_node, added: false });
+                }
+            }
+            ChatRequest::Typing { conversation } => {
+                let their_node = message.source().node.clone();
+                let conversation = match conversation {
+                    ConversationId::Direct(_) => ConversationId::Direct(their_node.clone()),
+                    conversation => conversation,
+                };
+                if state.recipients(&conversation).contains(&their_node) && state.record_typing(their_node.clone()) {
+                    push_ws_update(state, &WsUpdate::Typing {
+                        node: their_node,
+                        conversation,
+                        expires_in_ms: TYPING_TTL.as_millis() as u64,
+                    });
+                }
             }
             _ => println!("something else than a chat message")
         }
+    } else if let Ok(room_request) = serde_json::from_slice::<RoomRequest>(message.body()) {
+        println!("room request in handling");
+        handle_room_request(state, &message.source().node, room_request);
+    } else if let Ok(congregation_request) = serde_json::from_slice::<CongregationRequest>(message.body()) {
+        handle_congregation_request(state, &message.source().node, congregation_request);
+    } else if let Ok(AttachmentRequest::FetchAttachment { id }) = serde_json::from_slice::<AttachmentRequest>(message.body()) {
+        handle_fetch_attachment(state, &message.source().node, &id);    } else if let Ok(attachment_response) = serde_json::from_slice::<AttachmentResponse>(message.body()) {
+        handle_attachment_response(state, &message.source().node, message, attachment_response);
+    }
+}
+
+// answers a chat message the sender is waiting on with a receipt
+fn ack_chat_message(message: &Message, id: &str, status: DeliveryStatus) {
+    if let Message::Request { expects_response: Some(_), .. } = message {
+        let receipt = ChatRequest::Receipt { id: id.to_string(), status };
+        match serde_json::to_vec(&receipt) {
+            Ok(body) => {
+                if let Err(e) = Response::new().body(body).send() {
+                    println!("Failed to ack chat message: {:?}", e);
+                }
+            }
+            Err(e) => println!("Failed to serialize receipt: {:?}", e),
+        }
+    }
+}
+
+// always answer, so the fetching node doesn't sit out its whole timeout
+fn handle_fetch_attachment(state: &State, their_node: &NodeId, id: &str) {
+    // sealed like the message that carried it, so the bytes are never readable in transit
+    let file = state.attachment_for(id, their_node).and_then(|attachment| {
+        let drive = state.attachments_drive.as_ref()?;
+        let bytes = read_attachment(&attachment_path(drive, &attachment.owner, &attachment.id))?;
+        state.seal_blob(their_node, &attachment.id, &bytes)
+    });
+    let response = match file {
+        Some((sealed, ciphertext)) => Response::new()
+            .body(serde_json::to_vec(&AttachmentResponse::AttachmentFound(sealed)).unwrap())
+            .blob(LazyLoadBlob { mime: Some("application/octet-stream".to_string()), bytes: ciphertext }),
+        None => Response::new()
+            .body(serde_json::to_vec(&AttachmentResponse::AttachmentNotFound).unwrap()),
+    };
+    let _ = response.send();
+}
+
+fn handle_room_request(state: &mut State, their_node: &NodeId, room_request: RoomRequest) {
+    match room_request {
+        RoomRequest::RoomInvite { room_id, name, members } => {
+            if state.contacts.contains(their_node) && !state.rooms.contains_key(&room_id) {
+                state.room_invites.insert(room_id.clone(), RoomInvite { name: name.clone(), owner: their_node.clone(), members });
+                push_ws_update(state, &WsUpdate::RoomInvite { room_id, name, owner: their_node.clone() });
+            }
+        },
+        RoomRequest::RoomJoin { room_id } => {
+            match state.owned_room_mut(&room_id) {
+                Some(room) if room.invited.contains(their_node) => {
+                    room.invited.retain(|invited| invited != their_node);
+                    room.members.push(their_node.clone());
+                },
+                _ => {
+                    println!("{} tried to join {} without an invite", their_node, room_id);
+                    return;
+                }
+            }
+            broadcast_room_membership(state, &room_id, RoomEvent::Joined(their_node.clone()));
+        },
+        RoomRequest::RoomLeave { room_id } => {
+            match state.owned_room_mut(&room_id) {
+                Some(room) if room.members.contains(their_node) => room.members.retain(|member| member != their_node),
+                _ => return,
+            }
+            broadcast_room_membership(state, &room_id, RoomEvent::Left(their_node.clone()));
+        },
+        RoomRequest::RoomMembership { room_id, members, event } => {
+            match state.rooms.get_mut(&room_id) {
+                Some(room) if &room.owner == their_node => room.members = members,
+                _ => return,
+            }
+            push_ws_update(state, &WsUpdate::RoomEvent { room_id, event });
+        },
+        RoomRequest::RoomKick { room_id } => {
+            if state.rooms.get(&room_id).is_some_and(|room| &room.owner == their_node) {
+                state.remove_room(&room_id);
+                let event = RoomEvent::Kicked(state.node_id.clone());
+                push_ws_update(state, &WsUpdate::RoomEvent { room_id, event });
+            }
+        },
+        RoomRequest::RoomClosed { room_id } => {
+            if state.room_invites.get(&room_id).is_some_and(|invite| &invite.owner == their_node) {
+                state.room_invites.remove(&room_id);
+            }
+            if state.rooms.get(&room_id).is_some_and(|room| &room.owner == their_node) {
+                state.remove_room(&room_id);
+                push_ws_update(state, &WsUpdate::RoomClosed { room_id });
+            }
+        },
+    }
+}
+
+// re-ranks after stats changed and tells the UI about anything that matters to us
+fn refresh_standings(state: &mut State) {
+    for notification in state.update_standings() {
+        let message = notification.message();
+        println!("{}", message);
+        push_ws_update(state, &WsUpdate::RankNotification { notification, message });
+    }
+}
+
+// pushing live updates to every open UI
+fn push_ws_update(state: &State, update: &WsUpdate) {
+    match serde_json::to_vec(update) {
+        Ok(bytes) => {
+            for channel_id in &state.ws_channels {
+                send_ws_push(*channel_id, http::WsMessageType::Text, LazyLoadBlob {
+                    mime: Some("application/json".to_string()),
+                    bytes: bytes.clone(),
+                });
+            }
+        }
+        Err(e) => println!("Failed to serialize websocket update: {:?}", e),
+    }
+}
+
+// asks a contact for the chat we missed since we last synced with them
+fn request_history(state: &State, contact: &NodeId) {
+    send_to_shrine(contact, &ChatRequest::HistoryRequest { since: state.history_sync_point(contact) });
+}
+
+fn shrine_address(node: &NodeId) -> Address {
+    Address {
+        node: node.clone(),
+        process: ProcessId::from_str("updated_shrine:td_shrine:sharmouta.os").unwrap(),
+    }
+}
+
+// sealed whenever we share a key with the node. None if it carries content and we don't yet
+fn chat_request_body(state: &mut State, node: &NodeId, request: &ChatRequest) -> Option<Vec<u8>> {
+    match state.seal(node, request) {
+        Some(sealed) => serde_json::to_vec(&ChatRequest::Sealed(sealed)).ok(),
+        None if request.carries_content() => {
+            println!("no key shared with {} yet, holding back chat content", node);
+            send_key_exchange(state, node);
+            None
+        },
+        None => serde_json::to_vec(request).ok(),
+    }
+}
+
+// fire-and-forget chat request to another shrine, see chat_request_body
+fn send_chat_request(state: &mut State, node: &NodeId, request: &ChatRequest) {
+    let Some(body) = chat_request_body(state, node, request) else { return };
+    if let Err(e) = Request::new().body(body).target(&shrine_address(node)).send() {
+        println!("Failed to send chat request to {}: {:?}", node, e);
+    }
+}
+
+// offers our current key for this node, telling them which of theirs we already hold
+fn send_key_exchange(state: &mut State, node: &NodeId) {
+    let (public_key, known_key) = state.our_public_key(node);
+    send_to_shrine(node, &ContactRequest::KeyExchange { public_key, known_key });
+}
+
+// replaces keys past their lifetime, and keeps offering ours to anyone we can't seal for yet
+fn rotate_chat_keys(state: &mut State) {
+    let mut nodes = state.rotate_stale_keys();
+    let room_members = state.rooms.values().flat_map(|room| room.members.iter());
+    for node in state.contacts.iter().chain(room_members) {
+        if node != &state.node_id && !state.has_session(node) && !nodes.contains(node) {
+            nodes.push(node.clone());
+        }
+    }
+    for node in nodes {
+        send_key_exchange(state, &node);
+    }
+}
+
+// fire-and-forget peer message to another shrine
+fn send_to_shrine<T: Serialize + std::fmt::Debug>(node: &NodeId, request: &T) {
+    match serde_json::to_vec(request) {
+        Ok(body) => {
+            if let Err(e) = Request::new().body(body).target(&shrine_address(node)).send() {
+                println!("Failed to send {:?} to {}: {:?}", request, node, e);
+            }
+        }
+        Err(e) => println!("Failed to serialize {:?}: {:?}", request, e),
     }
 }
 
 // pushing your score to your contacts
 fn push_update_to_your_contacts(our: &Address, state: &State) {
-    let our_respects = state.stats.get(&state.node_id).unwrap_or(&LeaderboardEntry { respects: 0 });
-    let our_respect_update = ContactRequest::ContactUpdate(our_respects.clone());
+    let our_respects = state.stats.get(&state.node_id).cloned().unwrap_or_default();
+    let our_respect_update = ContactRequest::ContactUpdate(our_respects);
 
     for contact in &state.contacts {
         let their_addy = Address {
@@ -311,6 +1611,14 @@ fn push_update_to_your_contacts(our: &Address, state: &State) {
     }
 }
 
+// what we know about everyone's scores, for our contacts' extended leaderboards
+fn push_gossip_to_your_contacts(state: &mut State) {
+    state.prune_gossip();
+    for contact in &state.contacts {
+        send_to_shrine(contact, &ContactRequest::LeaderboardGossip(state.gossip_for(contact)));
+    }
+}
+
 // Resend pending contact requests
 fn resend_pending_requests(state: &mut State) {
      //println!("resending contact requests");
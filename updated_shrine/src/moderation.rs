@@ -0,0 +1,182 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use kinode_process_lib::{println, NodeId};
+use std::collections::{HashMap, HashSet};
+
+use crate::structs::{ChatMessage, State};
+
+pub const MAX_FILTERS: usize = 100;
+const MAX_PATTERN_LEN: usize = 200;
+/// compiled size cap, so a filter can't turn every ingest into a stall
+const REGEX_SIZE_LIMIT: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterKind {
+    Keyword, // case-insensitive, whole words where the keyword starts or ends with one
+    Regex,
+}
+
+/// ordered by strength, a hidden message stays hidden even if it also matches a collapse filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum FilterAction {
+    Collapse, // shown folded, the UI reveals it on request
+    Hide,     // left out of the chat views unless they ask for hidden messages
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatFilter {
+    pub id: String,
+    pub pattern: String,
+    pub kind: FilterKind,
+    pub action: FilterAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ModerationReason {
+    Muted,
+    Filter(String), // filter id
+}
+
+/// why a received message was hidden or collapsed. purely local, never sent on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Moderation {
+    pub action: FilterAction,
+    pub reason: ModerationReason,
+}
+
+/// POST /moderation
+#[derive(Debug, Deserialize)]
+pub enum ModerationBody {
+    Mute { node: NodeId },
+    Unmute { node: NodeId },
+    AddFilter { pattern: String, kind: FilterKind, action: FilterAction },
+    RemoveFilter { id: String },
+}
+
+/// GET /moderation
+#[derive(Debug, Serialize)]
+pub struct ModerationView<'a> {
+    pub mutes: &'a HashSet<NodeId>,
+    pub filters: &'a [ChatFilter],
+    pub filtered: Vec<&'a ChatMessage>,
+}
+
+/// filter id -> compiled pattern, rebuilt from the filters on fetch
+#[derive(Default)]
+pub struct FilterMatchers(HashMap<String, Regex>);
+
+impl std::fmt::Debug for FilterMatchers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FilterMatchers {{ {} filters }}", self.0.len())
+    }
+}
+
+fn compile(pattern: &str, kind: FilterKind) -> Result<Regex, String> {
+    if pattern.trim().is_empty() {
+        return Err("empty pattern".to_string());
+    }
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(format!("pattern is longer than {} bytes", MAX_PATTERN_LEN));
+    }
+    let source = match kind {
+        FilterKind::Keyword => {
+            let keyword = pattern.trim();
+            let start = if keyword.starts_with(|c: char| c.is_alphanumeric()) { r"\b" } else { "" };
+            let end = if keyword.ends_with(|c: char| c.is_alphanumeric()) { r"\b" } else { "" };
+            format!("{}{}{}", start, regex::escape(keyword), end)
+        },
+        FilterKind::Regex => pattern.to_string(),
+    };
+    RegexBuilder::new(&source)
+        .case_insensitive(kind == FilterKind::Keyword)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+impl ChatMessage {
+    pub fn is_hidden(&self) -> bool {
+        matches!(self.moderation, Some(Moderation { action: FilterAction::Hide, .. }))
+    }
+}
+
+impl State {
+    pub fn rebuild_filter_matchers(&mut self) {
+        let mut matchers = HashMap::new();
+        for filter in &self.chat_filters {
+            match compile(&filter.pattern, filter.kind) {
+                Ok(regex) => { matchers.insert(filter.id.clone(), regex); },
+                Err(e) => println!("dropping chat filter {}: {}", filter.id, e),
+            }
+        }
+        self.filter_matchers = FilterMatchers(matchers);
+    }
+
+    /// what our mutes and filters make of a message. our own messages are never moderated
+    pub fn moderate(&self, sender: &NodeId, content: &str) -> Option<Moderation> {
+        if sender == &self.node_id {
+            return None;
+        }
+        if self.mutes.contains(sender) {
+            return Some(Moderation { action: FilterAction::Hide, reason: ModerationReason::Muted });
+        }
+        self.chat_filters.iter()
+            .filter(|filter| self.filter_matchers.0.get(&filter.id).map_or(false, |regex| regex.is_match(content)))
+            .max_by_key(|filter| filter.action)
+            .map(|filter| Moderation { action: filter.action, reason: ModerationReason::Filter(filter.id.clone()) })
+    }
+
+    /// re-applies mutes and filters to everything we have, so a change works on old messages too
+    fn remoderate(&mut self) {
+        let verdicts: HashMap<String, Option<Moderation>> = self.all_chat_messages()
+            .map(|message| (message.id.clone(), self.moderate(&message.sender, &message.content)))
+            .collect();
+        for message in self.all_chat_messages_mut() {
+            message.moderation = verdicts.get(&message.id).cloned().flatten();
+        }
+    }
+
+    pub fn apply_moderation(&mut self, body: ModerationBody) -> Result<Option<ChatFilter>, String> {
+        let added = match body {
+            ModerationBody::Mute { node } => {
+                if node == self.node_id {
+                    return Err("can't mute yourself".to_string());
+                }
+                self.mutes.insert(node);
+                None
+            },
+            ModerationBody::Unmute { node } => {
+                self.mutes.remove(&node);
+                None
+            },
+            ModerationBody::AddFilter { pattern, kind, action } => {
+                if self.chat_filters.len() >= MAX_FILTERS {
+                    return Err(format!("at most {} filters", MAX_FILTERS));
+                }
+                let regex = compile(&pattern, kind)?;
+                let filter = ChatFilter { id: self.next_local_id(), pattern, kind, action };
+                self.filter_matchers.0.insert(filter.id.clone(), regex);
+                self.chat_filters.push(filter.clone());
+                Some(filter)
+            },
+            ModerationBody::RemoveFilter { id } => {
+                let before = self.chat_filters.len();
+                self.chat_filters.retain(|filter| filter.id != id);
+                if self.chat_filters.len() == before {
+                    return Err(format!("no filter {}", id));
+                }
+                self.filter_matchers.0.remove(&id);
+                None
+            },
+        };
+        self.remoderate();
+        Ok(added)
+    }
+
+    /// the filters and everything they're currently holding back, newest first
+    pub fn moderation_view(&self) -> ModerationView<'_> {
+        let mut filtered: Vec<&ChatMessage> = self.all_chat_messages().filter(|message| message.moderation.is_some()).collect();
+        filtered.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        ModerationView { mutes: &self.mutes, filters: &self.chat_filters, filtered }
+    }
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::structs::{LeaderboardEntry, State};
+
+pub const DEFAULT_OFFERING: &str = "candle";
+pub const MAX_OFFERINGS: usize = 32;
+const MAX_OFFERING_ID_LEN: usize = 32;
+const MAX_OFFERING_NAME_LEN: usize = 64;
+pub const MAX_OFFERING_WEIGHT: u64 = 1000;
+
+/// something that can be left at a shrine. the weight is what one of them adds to the score
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Offering {
+    pub id: String,
+    pub name: String,
+    pub weight: u64,
+}
+
+pub fn default_catalogue() -> BTreeMap<String, Offering> {
+    [("candle", "Candle", 1), ("flower", "Flower", 2), ("prayer", "Prayer", 3), ("code_offering", "Code offering", 5)]
+        .into_iter()
+        .map(|(id, name, weight)| (id.to_string(), Offering { id: id.to_string(), name: name.to_string(), weight }))
+        .collect()
+}
+
+/// POST /add_respect, the body is optional and an empty one leaves a candle
+#[derive(Debug, Default, Deserialize)]
+pub struct AddRespectBody {
+    #[serde(default)]
+    pub offering: Option<String>,
+}
+
+/// POST /offerings
+#[derive(Debug, Deserialize)]
+pub enum OfferingBody {
+    SetOffering { id: String, name: String, weight: u64 },
+    RemoveOffering { id: String },
+}
+
+impl State {
+    /// what `entry` is worth under our catalogue, less what rapid repeats withheld. offerings we
+    /// don't know weigh nothing, and entries from before offerings existed count each respect once
+    pub fn weighted_score(&self, entry: &LeaderboardEntry) -> u64 {
+        if entry.offerings.is_empty() {
+            return entry.respects;
+        }
+        let hundredths = entry.offerings.iter()
+            .map(|(id, count)| {
+                let credited = count.saturating_mul(100).saturating_sub(entry.diminished.get(id).copied().unwrap_or(0));
+                credited.saturating_mul(self.offering_catalogue.get(id).map_or(0, |offering| offering.weight))
+            })
+            .fold(0, u64::saturating_add);
+        hundredths / 100
+    }
+
+    /// our own score, recomputed whenever our offerings or weights change
+    pub fn rescore_our_entry(&mut self) {
+        let Some(entry) = self.stats.get(&self.node_id) else {
+            return;
+        };
+        let score = self.weighted_score(entry);
+        if let Some(entry) = self.stats.get_mut(&self.node_id) {
+            entry.score = score;
+        }
+    }
+
+    pub fn apply_offering_change(&mut self, body: OfferingBody) -> Result<Option<Offering>, String> {
+        let changed = match body {
+            OfferingBody::SetOffering { id, name, weight } => {
+                let id = id.trim().to_lowercase();
+                let name = name.trim().to_string();
+                if id.is_empty() || id.len() > MAX_OFFERING_ID_LEN || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    return Err(format!("offering ids are 1 to {} lowercase letters, digits or _", MAX_OFFERING_ID_LEN));
+                }
+                if id == "respects" || id == "score" {
+                    return Err(format!("{:?} is reserved for leaderboard sorting", id));
+                }
+                if name.is_empty() || name.chars().count() > MAX_OFFERING_NAME_LEN {
+                    return Err(format!("offering names are 1 to {} characters", MAX_OFFERING_NAME_LEN));
+                }
+                if weight > MAX_OFFERING_WEIGHT {
+                    return Err(format!("weights go up to {}", MAX_OFFERING_WEIGHT));
+                }
+                if !self.offering_catalogue.contains_key(&id) && self.offering_catalogue.len() >= MAX_OFFERINGS {
+                    return Err(format!("the catalogue is full at {} offerings", MAX_OFFERINGS));
+                }
+                let offering = Offering { id: id.clone(), name, weight };
+                self.offering_catalogue.insert(id, offering.clone());
+                Some(offering)
+            },
+            OfferingBody::RemoveOffering { id } => {
+                if id == DEFAULT_OFFERING {
+                    return Err(format!("{} is the default offering and can't be removed", DEFAULT_OFFERING));
+                }
+                self.offering_catalogue.remove(&id).ok_or(format!("no offering {:?}", id))?;
+                None
+            },
+        };
+        self.rescore_our_entry();
+        Ok(changed)
+    }
+}
@@ -0,0 +1,93 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+/// one word per line, in the spirit of TempleOS's GodWord vocabulary
+const GOD_WORDS: &str = include_str!("god_words.txt");
+/// every shrine ships the same seed, so the daily word agrees across all of them
+pub const DAILY_SEED: &str = "td_shrine";
+pub const MAX_GOD_WORDS: usize = 32;
+const SECS_PER_DAY: u64 = 86_400;
+
+/// what /god_says answers and what a /god chat command turns into
+#[derive(Debug, Serialize)]
+pub struct Revelation {
+    pub words: Vec<String>,
+    /// the utc day number, for the daily word
+    pub day: Option<u64>,
+}
+
+impl Revelation {
+    pub fn to_chat(&self) -> String {
+        match self.day {
+            Some(_) => format!("God says (word of the day): {}", self.words.join(" ")),
+            None => format!("God says: {}", self.words.join(" ")),
+        }
+    }
+}
+
+/// `count` words, fresh every time
+pub fn god_says(count: usize) -> Revelation {
+    let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+    Revelation { words: pick_words(nanos, count), day: None }
+}
+
+/// `count` words derived from the seed and the utc day alone, so every node holding the same seed
+/// gets the same words on the same day without having to talk to each other
+pub fn daily_word(seed: &str, count: usize, now: SystemTime) -> Revelation {
+    let day = now.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs() / SECS_PER_DAY;
+    let seed = fnv1a(format!("{}:{}", seed, day).as_bytes());
+    Revelation { words: pick_words(seed, count), day: Some(day) }
+}
+
+/// /god_says?count=3&daily=true&seed=...
+pub fn revelation_from_params(params: &HashMap<String, String>) -> Result<Revelation, String> {
+    let count = match params.get("count") {
+        Some(count) => count.parse::<usize>().map_err(|_| format!("invalid count: {}", count))?,
+        None => 1,
+    };
+    let count = count.clamp(1, MAX_GOD_WORDS);
+    match params.get("daily").map(String::as_str) {
+        Some("true") | Some("1") => {
+            let seed = params.get("seed").map(String::as_str).unwrap_or(DAILY_SEED);
+            Ok(daily_word(seed, count, SystemTime::now()))
+        },
+        Some("false") | Some("0") | None => Ok(god_says(count)),
+        Some(other) => Err(format!("invalid daily: {}", other)),
+    }
+}
+
+/// `/god`, `/god 5` or `/god daily` typed into the chat. None if the content isn't a /god command
+pub fn god_command(content: &str) -> Option<Revelation> {
+    let mut parts = content.split_whitespace();
+    if parts.next()? != "/god" {
+        return None;
+    }
+    let revelation = match parts.next() {
+        None => god_says(1),
+        Some("daily") => daily_word(DAILY_SEED, 1, SystemTime::now()),
+        Some(count) => god_says(count.parse::<usize>().unwrap_or(1).clamp(1, MAX_GOD_WORDS)),
+    };
+    Some(revelation)
+}
+
+fn pick_words(seed: u64, count: usize) -> Vec<String> {
+    let words: Vec<&str> = GOD_WORDS.lines().map(str::trim).filter(|word| !word.is_empty()).collect();
+    let mut state = seed;
+    (0..count)
+        .map(|_| words[(splitmix64(&mut state) % words.len() as u64) as usize].to_string())
+        .collect()
+}
+
+// both hand-rolled so the daily word can't drift between nodes built with different std or crate versions
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
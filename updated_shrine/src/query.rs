@@ -0,0 +1,165 @@
+use serde::Serialize;
+use kinode_process_lib::NodeId;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use crate::structs::{ChatMessage, ConversationId, State};
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 200;
+
+/// filters and cursor for /get_chat, parsed from the query string:
+/// `conversation` (shrine, direct:<node> or room:<room_id>), `sender`,
+/// `since`/`until` (unix millis, inclusive), `before` (a cursor from a previous page), `limit`
+/// and `include_hidden` for messages our moderation hid
+#[derive(Debug, Default)]
+pub struct ChatQuery {
+    pub conversation: Option<ConversationId>,
+    pub sender: Option<NodeId>,
+    pub since: Option<SystemTime>,
+    pub until: Option<SystemTime>,
+    pub before: Option<(SystemTime, String)>,
+    pub limit: usize,
+    pub include_hidden: bool,
+}
+
+/// one page of chat, oldest first so it can be dropped straight into the UI's chat_history.
+/// `next_cursor` fetches the page of older messages, None once there are no more
+#[derive(Debug, Serialize)]
+pub struct ChatPage {
+    pub chat_history: Vec<ChatMessage>,
+    pub next_cursor: Option<String>,
+}
+
+impl ChatQuery {
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self, String> {
+        let conversation = params.get("conversation").map(|value| parse_conversation(value)).transpose()?;
+        let since = params.get("since").map(|value| parse_millis(value)).transpose()?;
+        let until = params.get("until").map(|value| parse_millis(value)).transpose()?;
+        let before = params.get("before").map(|value| decode_cursor(value)).transpose()?;
+        let limit = match params.get("limit") {
+            Some(value) => value.parse::<usize>().map_err(|_| format!("bad limit {value:?}"))?.clamp(1, MAX_PAGE_SIZE),
+            None => DEFAULT_PAGE_SIZE,
+        };
+        Ok(ChatQuery {
+            conversation,
+            sender: params.get("sender").cloned(),
+            since,
+            until,
+            before,
+            limit,
+            include_hidden: params.get("include_hidden").map_or(false, |value| value == "true"),
+        })
+    }
+
+    fn matches(&self, chat_message: &ChatMessage) -> bool {
+        self.conversation.as_ref().map_or(true, |conversation| &chat_message.conversation == conversation)
+            && self.sender.as_ref().map_or(true, |sender| &chat_message.sender == sender)
+            && self.since.map_or(true, |since| chat_message.timestamp >= since)
+            && self.until.map_or(true, |until| chat_message.timestamp <= until)
+            && self.before.as_ref().map_or(true, |before| sort_key(chat_message) < (before.0, before.1.as_str()))
+            && (self.include_hidden || !chat_message.is_hidden())
+    }
+}
+
+impl State {
+    /// newest matching messages first, then handed back oldest first
+    pub fn query_chat(&self, query: &ChatQuery) -> ChatPage {
+        let mut matching: Vec<&ChatMessage> = self.all_chat_messages().filter(|message| query.matches(message)).collect();
+        matching.sort_by(|a, b| sort_key(b).cmp(&sort_key(a)));
+        let has_more = matching.len() > query.limit;
+        matching.truncate(query.limit);
+        let next_cursor = if has_more { matching.last().map(|message| encode_cursor(message)) } else { None };
+        matching.reverse();
+        ChatPage {
+            chat_history: matching.into_iter().cloned().collect(),
+            next_cursor,
+        }
+    }
+}
+
+// (timestamp, id) is unique and stable, so pages never skip or repeat a message
+fn sort_key(chat_message: &ChatMessage) -> (SystemTime, &str) {
+    (chat_message.timestamp, chat_message.id.as_str())
+}
+
+// "<unix nanos>_<message id>"
+fn encode_cursor(chat_message: &ChatMessage) -> String {
+    let nanos = chat_message.timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos();
+    format!("{}_{}", nanos, chat_message.id)
+}
+
+fn decode_cursor(cursor: &str) -> Result<(SystemTime, String), String> {
+    let (nanos, id) = cursor.split_once('_').ok_or_else(|| format!("bad cursor {cursor:?}"))?;
+    let nanos = nanos.parse::<u64>().map_err(|_| format!("bad cursor {cursor:?}"))?;
+    Ok((SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos), id.to_string()))
+}
+
+fn parse_millis(value: &str) -> Result<SystemTime, String> {
+    let millis = value.parse::<u64>().map_err(|_| format!("bad timestamp {value:?}"))?;
+    Ok(SystemTime::UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+pub fn parse_conversation(value: &str) -> Result<ConversationId, String> {
+    match value.split_once(':') {
+        None if value == "shrine" => Ok(ConversationId::Shrine),
+        Some(("direct", node)) if !node.is_empty() => Ok(ConversationId::Direct(node.to_string())),
+        Some(("room", room_id)) if !room_id.is_empty() => Ok(ConversationId::Room(room_id.to_string())),
+        _ => Err(format!("bad conversation {value:?}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message_at(id: &str, millis: u64) -> ChatMessage {
+        let mut chat_message = ChatMessage::new(id.to_string(), "alice.os".to_string(), "hi".to_string(), ConversationId::Shrine);
+        chat_message.timestamp = SystemTime::UNIX_EPOCH + Duration::from_millis(millis);
+        chat_message
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let chat_message = message_at("alice.os:7", 1_700_000_000_123);
+        let cursor = encode_cursor(&chat_message);
+        assert_eq!(cursor, "1700000000123000000_alice.os:7");
+        assert_eq!(decode_cursor(&cursor).unwrap(), (chat_message.timestamp, chat_message.id.clone()));
+        // ids may contain underscores, only the first one splits
+        assert_eq!(decode_cursor("5_my_node.os:1").unwrap().1, "my_node.os:1");
+    }
+
+    #[test]
+    fn bad_cursors_are_refused() {
+        assert!(decode_cursor("").is_err());
+        assert!(decode_cursor("no-underscore").is_err());
+        assert!(decode_cursor("soon_alice.os:1").is_err());
+    }
+
+    #[test]
+    fn pages_never_skip_or_repeat_a_message() {
+        let mut state = State::new("alice.os".to_string());
+        // two messages share a timestamp, the id breaks the tie
+        for (id, millis) in [("alice.os:1", 10), ("alice.os:2", 20), ("alice.os:3", 20), ("alice.os:4", 30), ("alice.os:5", 40)] {
+            state.chat_history.push(message_at(id, millis));
+        }
+        let mut query = ChatQuery { limit: 2, ..ChatQuery::default() };
+        let mut seen = Vec::new();
+        loop {
+            let page = state.query_chat(&query);
+            seen.splice(0..0, page.chat_history.iter().map(|message| message.id.clone()));
+            let Some(cursor) = page.next_cursor else { break };
+            query.before = Some(decode_cursor(&cursor).unwrap());
+        }
+        assert_eq!(seen, vec!["alice.os:1", "alice.os:2", "alice.os:3", "alice.os:4", "alice.os:5"]);
+    }
+
+    #[test]
+    fn conversations_parse_from_params() {
+        assert_eq!(parse_conversation("shrine").unwrap(), ConversationId::Shrine);
+        assert_eq!(parse_conversation("direct:bob.os").unwrap(), ConversationId::Direct("bob.os".to_string()));
+        assert_eq!(parse_conversation("room:bob.os:games").unwrap(), ConversationId::Room("bob.os:games".to_string()));
+        assert!(parse_conversation("direct:").is_err());
+        assert!(parse_conversation("lobby").is_err());
+    }
+}
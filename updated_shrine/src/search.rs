@@ -0,0 +1,256 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::structs::{ChatMessage, ConversationId, State};
+
+pub const DEFAULT_SEARCH_LIMIT: usize = 20;
+pub const MAX_SEARCH_LIMIT: usize = 100;
+/// a term found in the sender's name counts this many content hits
+const SENDER_WEIGHT: f32 = 2.0;
+/// characters of context kept before the first hit in a snippet
+const SNIPPET_CONTEXT: usize = 30;
+const SNIPPET_LEN: usize = 120;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Posting {
+    content_hits: u32,
+    in_sender: bool,
+}
+
+/// token -> message id -> where it was found. kept up to date as messages are
+/// added, edited, deleted or evicted, and rebuilt from the histories on fetch
+#[derive(Default)]
+pub struct SearchIndex {
+    postings: BTreeMap<String, HashMap<String, Posting>>,
+    indexed: HashMap<String, Vec<String>>, // message id -> its tokens, for unindexing
+}
+
+impl std::fmt::Debug for SearchIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SearchIndex {{ {} messages, {} tokens }}", self.indexed.len(), self.postings.len())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub message: ChatMessage,
+    pub score: f32,
+    pub snippet: String,
+    /// [start, end) char offsets into the snippet
+    pub highlights: Vec<(usize, usize)>,
+}
+
+impl SearchIndex {
+    pub fn add(&mut self, id: &str, sender: &str, content: &str) {
+        self.remove(id);
+        let mut postings: HashMap<String, Posting> = HashMap::new();
+        for (token, _, _) in tokenize(content) {
+            postings.entry(token).or_default().content_hits += 1;
+        }
+        for (token, _, _) in tokenize(sender) {
+            postings.entry(token).or_default().in_sender = true;
+        }
+        let tokens = postings.keys().cloned().collect();
+        for (token, posting) in postings {
+            self.postings.entry(token).or_default().insert(id.to_string(), posting);
+        }
+        self.indexed.insert(id.to_string(), tokens);
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        let Some(tokens) = self.indexed.remove(id) else { return };
+        for token in tokens {
+            if let Some(postings) = self.postings.get_mut(&token) {
+                postings.remove(id);
+                if postings.is_empty() {
+                    self.postings.remove(&token);
+                }
+            }
+        }
+    }
+
+    /// every message matching all terms, scored by tf-idf. the last term also matches
+    /// as a prefix so results keep up while the user is still typing
+    fn search(&self, terms: &[String]) -> HashMap<String, f32> {
+        let total = self.indexed.len().max(1) as f32;
+        let mut scores: Option<HashMap<String, f32>> = None;
+        for (i, term) in terms.iter().enumerate() {
+            let prefix = i + 1 == terms.len();
+            let mut hits: HashMap<&str, Posting> = HashMap::new();
+            for (token, postings) in self.postings.range(term.clone()..) {
+                if token != term && !(prefix && token.starts_with(term.as_str())) {
+                    break;
+                }
+                for (id, posting) in postings {
+                    let hit = hits.entry(id.as_str()).or_default();
+                    hit.content_hits += posting.content_hits;
+                    hit.in_sender |= posting.in_sender;
+                }
+            }
+            let idf = (1.0 + total / hits.len().max(1) as f32).ln();
+            let term_scores: HashMap<String, f32> = hits.into_iter()
+                .map(|(id, hit)| {
+                    let weight = hit.content_hits as f32 + if hit.in_sender { SENDER_WEIGHT } else { 0.0 };
+                    (id.to_string(), idf * weight)
+                })
+                .collect();
+            scores = Some(match scores {
+                None => term_scores,
+                Some(previous) => previous.into_iter()
+                    .filter_map(|(id, score)| term_scores.get(&id).map(|term_score| (id, score + term_score)))
+                    .collect(),
+            });
+        }
+        scores.unwrap_or_default()
+    }
+}
+
+impl State {
+    pub fn reindex_chat(&mut self) {
+        let mut search_index = SearchIndex::default();
+        for chat_message in self.all_chat_messages().filter(|message| !message.deleted) {
+            search_index.add(&chat_message.id, &chat_message.sender, &chat_message.content);
+        }
+        self.search_index = search_index;
+    }
+
+    /// best matches first, newest first among equal scores
+    pub fn search_chat(&self, query: &str, conversation: Option<&ConversationId>, limit: usize) -> Vec<SearchHit> {
+        let mut terms: Vec<String> = Vec::new();
+        for (token, _, _) in tokenize(query) {
+            if !terms.contains(&token) {
+                terms.push(token);
+            }
+        }
+        if terms.is_empty() {
+            return Vec::new();
+        }
+        let scores = self.search_index.search(&terms);
+        let mut hits: Vec<SearchHit> = self.all_chat_messages()
+            .filter(|message| !message.deleted && !message.is_hidden() && conversation.map_or(true, |conversation| &message.conversation == conversation))
+            .filter_map(|message| scores.get(&message.id).map(|score| (message, *score)))
+            .map(|(message, score)| {
+                let (snippet, highlights) = snippet(&message.content, &terms);
+                SearchHit { message: message.clone(), score, snippet, highlights }
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| b.message.timestamp.cmp(&a.message.timestamp)));
+        hits.truncate(limit);
+        hits
+    }
+}
+
+/// lowercased alphanumeric runs with their [start, end) char offsets
+fn tokenize(text: &str) -> Vec<(String, usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+    let mut len = 0;
+    for (i, c) in text.chars().enumerate() {
+        if c.is_alphanumeric() {
+            if current.is_empty() {
+                start = i;
+            }
+            current.extend(c.to_lowercase());
+        } else if !current.is_empty() {
+            tokens.push((std::mem::take(&mut current), start, i));
+        }
+        len = i + 1;
+    }
+    if !current.is_empty() {
+        tokens.push((current, start, len));
+    }
+    tokens
+}
+
+/// a window of the content around the first hit, with every hit inside it marked
+fn snippet(content: &str, terms: &[String]) -> (String, Vec<(usize, usize)>) {
+    let last = terms.len() - 1;
+    let spans: Vec<(usize, usize)> = tokenize(content).into_iter()
+        .filter(|(token, _, _)| terms.iter().enumerate().any(|(i, term)| token == term || (i == last && token.starts_with(term.as_str()))))
+        .map(|(_, start, end)| (start, end))
+        .collect();
+    let chars: Vec<char> = content.chars().collect();
+    let start = spans.first().map_or(0, |(first, _)| first.saturating_sub(SNIPPET_CONTEXT));
+    let end = (start + SNIPPET_LEN).min(chars.len());
+
+    let mut snippet = String::new();
+    let offset = if start > 0 {
+        snippet.push('…');
+        1
+    } else {
+        0
+    };
+    snippet.extend(&chars[start..end]);
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    let highlights = spans.into_iter()
+        .filter(|(span_start, span_end)| *span_start >= start && *span_end <= end)
+        .map(|(span_start, span_end)| (span_start - start + offset, span_end - start + offset))
+        .collect();
+    (snippet, highlights)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|term| term.to_string()).collect()
+    }
+
+    #[test]
+    fn tokenize_lowercases_alphanumeric_runs_with_char_offsets() {
+        assert_eq!(tokenize("Héllo, wörld 42!"), vec![
+            ("héllo".to_string(), 0, 5),
+            ("wörld".to_string(), 7, 12),
+            ("42".to_string(), 13, 15),
+        ]);
+        assert!(tokenize(" ,.! ").is_empty());
+    }
+
+    #[test]
+    fn search_needs_every_term_and_prefixes_the_last() {
+        let mut index = SearchIndex::default();
+        index.add("a", "alice.os", "the candle burns");
+        index.add("b", "bob.os", "a candle and a flower");
+        index.add("c", "carol.os", "flowers only");
+        let hits = index.search(&terms(&["candle", "flow"]));
+        assert_eq!(hits.keys().collect::<Vec<_>>(), vec!["b"]);
+        // only the last term matches as a prefix
+        assert!(index.search(&terms(&["flow", "candle"])).is_empty());
+        assert!(index.search(&terms(&["flow"])).contains_key("c"));
+        assert!(!index.search(&terms(&["flow", "only"])).contains_key("b"));
+    }
+
+    #[test]
+    fn search_weighs_the_sender_and_forgets_removed_messages() {
+        let mut index = SearchIndex::default();
+        index.add("a", "alice.os", "hi");
+        index.add("b", "bob.os", "alice said hi");
+        let hits = index.search(&terms(&["alice"]));
+        assert!(hits["a"] > hits["b"]);
+        index.remove("a");
+        assert_eq!(index.search(&terms(&["alice"])).keys().collect::<Vec<_>>(), vec!["b"]);
+        // an edit replaces the old tokens
+        index.add("b", "bob.os", "bye");
+        assert!(index.search(&terms(&["alice"])).is_empty());
+    }
+
+    #[test]
+    fn snippet_marks_hits_in_a_window_around_the_first() {
+        let (text, highlights) = snippet("Candles everywhere, one candle", &terms(&["candle"]));
+        assert_eq!(text, "Candles everywhere, one candle");
+        // the last term is a prefix, so "Candles" counts too
+        assert_eq!(highlights, vec![(0, 7), (24, 30)]);
+
+        let content = format!("{} needle {}", "x ".repeat(40), "y ".repeat(100));
+        let (text, highlights) = snippet(&content, &terms(&["nee"]));
+        assert!(text.starts_with('…') && text.ends_with('…'));
+        assert_eq!(text.chars().count(), SNIPPET_LEN + 2);
+        let (start, end) = highlights[0];
+        assert_eq!(text.chars().skip(start).take(end - start).collect::<String>(), "needle");
+    }
+}
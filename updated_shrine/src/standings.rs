@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use kinode_process_lib::NodeId;
+use std::collections::{HashMap, VecDeque};
+use std::time::SystemTime;
+
+use crate::leaderboard::LeaderboardQuery;
+use crate::structs::State;
+
+/// rank moves kept for the UI, newest last
+pub const RANK_HISTORY_LEN: usize = 50;
+pub const RANK_NOTIFICATIONS_LEN: usize = 20;
+
+/// a node moving on our main board: contacts, all-time respects, competition ranking
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankChange {
+    pub node: NodeId,
+    pub from: Option<usize>, // None when it just appeared
+    pub to: Option<usize>,   // None when it left the board
+    pub at: SystemTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RankNotificationKind {
+    OvertookUs { node: NodeId },
+    WeOvertook { node: NodeId },
+    ReachedFirst,
+    LostFirst { to: NodeId },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankNotification {
+    pub kind: RankNotificationKind,
+    pub rank: usize, // ours, after the change
+    pub at: SystemTime,
+}
+
+impl RankNotification {
+    pub fn message(&self) -> String {
+        match &self.kind {
+            RankNotificationKind::OvertookUs { node } => format!("{} overtook you, you're #{} now", node, self.rank),
+            RankNotificationKind::WeOvertook { node } => format!("you overtook {}, you're #{} now", node, self.rank),
+            RankNotificationKind::ReachedFirst => "you reached #1".to_string(),
+            RankNotificationKind::LostFirst { to } => format!("{} took #1 from you", to),
+        }
+    }
+}
+
+/// GET /rank_history
+#[derive(Debug, Serialize)]
+pub struct RankHistoryView<'a> {
+    pub standings: &'a HashMap<NodeId, usize>,
+    pub changes: &'a VecDeque<RankChange>,
+    pub notifications: &'a VecDeque<RankNotification>,
+}
+
+impl State {
+    /// re-ranks the main board after `stats` changed, records who moved and returns what's worth
+    /// telling us about. the first run only takes a baseline
+    pub fn update_standings(&mut self) -> Vec<RankNotification> {
+        let now = SystemTime::now();
+        let standings: HashMap<NodeId, usize> = self.ranked_leaderboard(&LeaderboardQuery::default())
+            .into_iter()
+            .map(|entry| (entry.node, entry.rank))
+            .collect();
+        let previous = std::mem::replace(&mut self.standings, standings);
+        if previous.is_empty() {
+            return Vec::new();
+        }
+
+        let moved = self.standings.iter()
+            .filter(|(node, rank)| previous.get(*node) != Some(*rank))
+            .map(|(node, rank)| RankChange { node: node.clone(), from: previous.get(node).copied(), to: Some(*rank), at: now });
+        let gone = previous.iter()
+            .filter(|(node, _)| !self.standings.contains_key(*node))
+            .map(|(node, rank)| RankChange { node: node.clone(), from: Some(*rank), to: None, at: now });
+        let mut changes: Vec<RankChange> = moved.chain(gone).collect();
+        changes.sort_by(|a, b| a.to.cmp(&b.to).then_with(|| a.node.cmp(&b.node)));
+        self.rank_changes.extend(changes);
+        let overflow = self.rank_changes.len().saturating_sub(RANK_HISTORY_LEN);
+        self.rank_changes.drain(..overflow);
+
+        let (Some(&was), Some(&ours)) = (previous.get(&self.node_id), self.standings.get(&self.node_id)) else {
+            return Vec::new();
+        };
+        let mut board: Vec<(&NodeId, usize)> = self.standings.iter().map(|(node, rank)| (node, *rank)).collect();
+        board.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(b.0)));
+        let mut kinds = Vec::new();
+        // only nodes that were on the board both times can pass or be passed
+        for (node, rank) in board {
+            let Some(&before) = previous.get(node).filter(|_| node != &self.node_id) else {
+                continue;
+            };
+            if before > was && rank < ours {
+                kinds.push(RankNotificationKind::OvertookUs { node: node.clone() });
+            } else if before < was && rank > ours {
+                kinds.push(RankNotificationKind::WeOvertook { node: node.clone() });
+            }
+        }
+        if ours == 1 && was != 1 {
+            kinds.push(RankNotificationKind::ReachedFirst);
+        } else if was == 1 && ours != 1 {
+            if let Some(first) = self.standings.iter().filter(|(_, rank)| **rank == 1).map(|(node, _)| node).min() {
+                kinds.push(RankNotificationKind::LostFirst { to: first.clone() });
+            }
+        }
+
+        let notifications: Vec<RankNotification> = kinds.into_iter()
+            .map(|kind| RankNotification { kind, rank: ours, at: now })
+            .collect();
+        self.rank_notifications.extend(notifications.iter().cloned());
+        let overflow = self.rank_notifications.len().saturating_sub(RANK_NOTIFICATIONS_LEN);
+        self.rank_notifications.drain(..overflow);
+        notifications
+    }
+
+    pub fn rank_history_view(&self) -> RankHistoryView<'_> {
+        RankHistoryView { standings: &self.standings, changes: &self.rank_changes, notifications: &self.rank_notifications }
+    }
+}
@@ -1,24 +1,310 @@
 use serde::{Serialize, Deserialize};
-use kinode_process_lib::{get_state, set_state, NodeId};
-use std::collections::{HashMap, HashSet};
-use std::time::SystemTime;
+use kinode_process_lib::{get_state, set_state, println, NodeId};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::{Duration, SystemTime};
 
+use crate::achievements::{Achievement, AchievementKind, Streak};
+use crate::allowance::{RespectRefused, RespectRules};
+use crate::congregations::{Congregation, CongregationInvite};
+use crate::crypto::{ContactKeys, SealedBlob, SealedChat};
+use crate::gossip::GossipEntry;
+use crate::leaderboard::SeenRank;
+use crate::moderation::{ChatFilter, FilterMatchers, Moderation};
+use crate::offerings::{default_catalogue, Offering, DEFAULT_OFFERING};
+use crate::search::SearchIndex;
+use crate::standings::{RankChange, RankNotification};
+use crate::windows::WindowCounts;
+use crate::validation::{parse_mentions, sanitize_reactions, sanitize_remote_message, ContentError};
+
+/// how long a typing indicator stays alive without being refreshed
+pub const TYPING_TTL: Duration = Duration::from_secs(5);
+/// minimum gap between two typing notifications from the same node
+pub const TYPING_MIN_INTERVAL: Duration = Duration::from_secs(2);
+/// seconds a contact has to ack a chat message before it goes back to Queued
+pub const DELIVERY_TIMEOUT: u64 = 15;
+/// how many message ids we remember for dedup, beyond what's still in the histories
+pub const SEEN_MESSAGE_IDS: usize = 1000;
+/// longest reaction we accept, in bytes. enough for any emoji sequence
+pub const MAX_REACTION_LEN: usize = 32;
+/// how far before the last sync we ask a contact to backfill from, to cover clock skew
+pub const HISTORY_SYNC_SLACK: Duration = Duration::from_secs(300);
+/// this long without a ContactUpdate (sent every 30s) and we assume one of us was offline
+pub const CONTACT_SILENCE: Duration = Duration::from_secs(90);
+/// messages kept per conversation
+pub const HISTORY_LEN: usize = 50;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
+    /// "{sender}:{seq}", unique per sender across restarts since the seq is seeded from the clock
+    #[serde(default)]
+    pub id: String,
     pub sender: NodeId,
     pub content: String,
     pub timestamp: SystemTime,
+    /// per-recipient delivery state. on our own messages it's keyed by contact,
+    /// on received ones it only holds our own node
+    #[serde(default)]
+    pub receipts: HashMap<NodeId, DeliveryStatus>,
+    #[serde(default)]
+    pub conversation: ConversationId,
+    /// when the current content was set by an edit
+    #[serde(default)]
+    pub edited_at: Option<SystemTime>,
+    /// previous contents, oldest first
+    #[serde(default)]
+    pub edit_history: Vec<String>,
+    /// tombstone, content and history are cleared
+    #[serde(default)]
+    pub deleted: bool,
+    /// emoji -> nodes that reacted with it
+    #[serde(default)]
+    pub reactions: BTreeMap<String, Vec<NodeId>>,
+    /// nodes @mentioned in the content, re-parsed on every edit
+    #[serde(default)]
+    pub mentions: Vec<NodeId>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// set on ingest by our mutes and filters
+    #[serde(default)]
+    pub moderation: Option<Moderation>,
+}
+
+/// a file the owner keeps in their VFS drive. recipients only get this metadata
+/// and fetch the bytes from the owner the first time the UI asks for them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: String, // hex, unique per owner
+    pub owner: NodeId,
+    pub name: String,
+    pub mime: String,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AttachmentRequest {
+    FetchAttachment { id: String },
+}
+
+/// the bytes ride along as the response's blob, sealed for the fetching node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AttachmentResponse {
+    AttachmentFound(SealedBlob),
+    AttachmentNotFound,
+}
+
+impl ChatMessage {
+    pub fn new(id: String, sender: NodeId, content: String, conversation: ConversationId) -> Self {
+        ChatMessage {
+            mentions: parse_mentions(&content),
+            id,
+            sender,
+            content,
+            timestamp: SystemTime::now(),
+            receipts: HashMap::new(),
+            conversation,
+            edited_at: None,
+            edit_history: Vec::new(),
+            deleted: false,
+            reactions: BTreeMap::new(),
+            attachments: Vec::new(),
+            moderation: None,
+        }
+    }
+
+    /// still waiting for us to read it, mentions us and got past our filters
+    pub fn is_unread_mention_of(&self, node: &NodeId) -> bool {
+        self.mentions.contains(node) && self.receipts.get(node) == Some(&DeliveryStatus::Delivered) && self.moderation.is_none()
+    }
+}
+
+/// which chat a message belongs to. a Direct message carries the recipient on the wire
+/// and is re-keyed to the sender once it arrives, so both sides file it under the other node
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ConversationId {
+    #[default]
+    Shrine, // the shared room every contact sees
+    Direct(NodeId),
+    Room(String), // a named group room, by room id
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub history: Vec<ChatMessage>,
+    pub unread: usize,
+}
+
+/// a named group room. the owner is the authority on membership and re-broadcasts every change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Room {
+    pub name: String,
+    pub owner: NodeId,
+    pub members: Vec<NodeId>, // includes the owner
+    pub invited: Vec<NodeId>, // only tracked by the owner
+    pub history: Vec<ChatMessage>,
+    pub unread: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomInvite {
+    pub name: String,
+    pub owner: NodeId,
+    pub members: Vec<NodeId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RoomEvent {
+    Joined(NodeId),
+    Left(NodeId),
+    Kicked(NodeId),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RoomRequest {
+    RoomInvite { room_id: String, name: String, members: Vec<NodeId> },
+    RoomJoin { room_id: String },
+    RoomLeave { room_id: String },
+    RoomKick { room_id: String },
+    RoomMembership { room_id: String, members: Vec<NodeId>, event: RoomEvent },
+    RoomClosed { room_id: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationSummary {
+    pub conversation: ConversationId,
+    pub unread: usize,
+    pub unread_mentions: usize,
+    pub last_message: Option<ChatMessage>,
+}
+
+/// ordered so a receipt can only move forward
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DeliveryStatus {
+    Queued,
+    Sent,
+    Delivered,
+    Read,
+    /// the recipient refused it. final, it's never retried
+    Rejected,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ChatRequest {
     ChatMessageReceived(ChatMessage),
+    /// a Direct conversation names the recipient, who reads it as a conversation with the sender
+    Typing { conversation: ConversationId },
+    /// ack for the message with this id
+    Receipt { id: String, status: DeliveryStatus },
+    /// only applied when the verified source is the original sender
+    EditMessage { id: String, content: String, edited_at: SystemTime },
+    DeleteMessage { id: String },
+    /// the reactor is the verified source
+    AddReaction { id: String, emoji: String },
+    RemoveReaction { id: String, emoji: String },
+    /// ask a contact for every message we share with them since then
+    HistoryRequest { since: SystemTime },
+    HistoryResponse(Vec<ChatMessage>),
+    /// any of the above, end-to-end encrypted for the receiving node
+    Sealed(SealedChat),
+}
+
+/// pushed to the UI over the websocket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WsUpdate {
+    Typing { node: NodeId, conversation: ConversationId, expires_in_ms: u64 },
+    Receipt { id: String, recipient: NodeId, status: DeliveryStatus },
+    RoomInvite { room_id: String, name: String, owner: NodeId },
+    RoomEvent { room_id: String, event: RoomEvent },
+    RoomClosed { room_id: String },
+    MessageEdited { id: String, content: String, edited_at: SystemTime },
+    MessageDeleted { id: String },
+    /// an attachment we asked its owner for arrived, or won't
+    AttachmentFetched { owner: NodeId, id: String, found: bool },
+    Reaction { id: String, emoji: String, node: NodeId, added: bool },
+    /// high priority, the UI should surface it even if the conversation is busy or muted
+    Mention { id: String, sender: NodeId, conversation: ConversationId, content: String },
+    HistorySynced { node: NodeId, added: usize },
+    /// `message` is ready to show, `notification` has the details
+    RankNotification { notification: RankNotification, message: String },
+    CongregationInvite { congregation_id: String, name: String, founder: NodeId },
+    CongregationMembership { congregation_id: String, members: Vec<NodeId> },
+    /// we left, were removed, or the founder disbanded it
+    CongregationGone { congregation_id: String },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessageBody {
+    #[serde(default)]
     pub content: String,
+    #[serde(default)]
+    pub conversation: ConversationId,
+    /// ids from /upload_attachment
+    #[serde(default)]
+    pub attachments: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EditChatMessageBody {
+    pub id: String,
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteChatMessageBody {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReactionBody {
+    pub id: String,
+    pub emoji: String,
+    #[serde(default)]
+    pub remove: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRoomBody {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoomBody {
+    pub room_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoomMemberBody {
+    pub room_id: String,
+    pub node: NodeId,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MarkReadBody {
+    #[serde(default)]
+    pub conversation: ConversationId,
+}
+
+/// POST /typing, no body means the shrine room
+#[derive(Debug, Default, Deserialize)]
+pub struct TypingBody {
+    #[serde(default)]
+    pub conversation: ConversationId,
+}
+
+/// Hide keeps the entry updating but off our boards, Delete drops it and ignores anything
+/// that would bring it back. Restore undoes either
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum RemoveMode {
+    #[default]
+    Hide,
+    Delete,
+    Restore,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveEntryBody {
+    pub node: NodeId,
+    #[serde(default)]
+    pub mode: RemoveMode,
 }
 
 #[derive(Debug, Deserialize)]
@@ -31,11 +317,31 @@ pub enum ContactRequest {
     RequestContact(NodeId),
     ContactAccepted(NodeId),
     ContactUpdate(LeaderboardEntry),
+    /// entries we know, ours included, for the receiver's extended leaderboard
+    LeaderboardGossip(Vec<GossipEntry>),
+    /// our current public key for the receiving node, hex. `known_key` is the id of theirs we
+    /// hold, so they can tell whether we need theirs again
+    KeyExchange { public_key: String, known_key: Option<String> },
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
 pub struct LeaderboardEntry {
-    pub respects: u64,
+    pub respects: u64, // all time
+    #[serde(default)]
+    pub windows: WindowCounts,
+    #[serde(default)]
+    pub streak: Streak,
+    #[serde(default)]
+    pub achievements: Vec<Achievement>,
+    /// offering id -> how many were left, all time
+    #[serde(default)]
+    pub offerings: BTreeMap<String, u64>,
+    /// weighted by the owner's catalogue. boards recompute it with their own weights
+    #[serde(default)]
+    pub score: u64,
+    /// offering id -> hundredths of an offering withheld from the score for rapid repeats
+    #[serde(default)]
+    pub diminished: BTreeMap<String, u64>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -44,32 +350,107 @@ pub struct State {
     pub discoverable: bool,
     pub contacts: Vec<NodeId>,
     pub stats: HashMap<NodeId, LeaderboardEntry>,
+    pub leaderboard_seen: HashMap<String, HashMap<NodeId, SeenRank>>, // board -> where each node last stood on it
+    pub offering_catalogue: BTreeMap<String, Offering>,
+    pub standings: HashMap<NodeId, usize>, // ranks on the main board as of the last stats change
+    pub rank_changes: VecDeque<RankChange>,
+    pub rank_notifications: VecDeque<RankNotification>,
+    pub hidden_entries: HashSet<NodeId>, // still tracked, just not on our boards
+    pub deleted_entries: HashSet<NodeId>, // dropped, and ContactUpdates or gossip don't bring them back
+    pub respect_events: Vec<SystemTime>, // ours, only as far back as the windows need
+    pub respect_rules: RespectRules,
+    pub stats_updated_at: HashMap<NodeId, SystemTime>, // contacts, when their last ContactUpdate came in
+    pub gossip_entries: HashMap<NodeId, GossipEntry>, // nodes beyond our contacts, by origin
+    pub gossip_shareable: HashMap<NodeId, bool>, // whether each contact lets us pass their entry on
     pub pending_contact_requests: Vec<NodeId>,  
     pub incoming_contact_requests: Vec<NodeId>,
-    pub chat_history: Vec<ChatMessage>,
+    pub chat_history: Vec<ChatMessage>, // the shrine room
+    pub chat_unread: usize,
+    pub direct_conversations: HashMap<NodeId, Conversation>,
+    pub rooms: HashMap<String, Room>,
+    pub room_invites: HashMap<String, RoomInvite>,
+    pub congregations: HashMap<String, Congregation>,
+    pub congregation_invites: HashMap<String, CongregationInvite>,
+    pub next_message_seq: u64,
+    pub seen_message_ids: VecDeque<String>,
+    pub history_synced_at: HashMap<NodeId, SystemTime>,
+    pub rejected_remote_messages: BTreeMap<String, u64>, // reason -> count
+    pub attachments: HashMap<String, Attachment>, // the ones we uploaded, by id
+    pub mutes: HashSet<NodeId>,
+    pub chat_filters: Vec<ChatFilter>,
+    #[serde(skip)]
+    pub filter_matchers: FilterMatchers,
+    pub chat_keys: HashMap<NodeId, ContactKeys>, // never handed to the UI
+    #[serde(skip)]
+    pub attachments_drive: Option<String>,
+    #[serde(skip)]
+    pub search_index: SearchIndex,
+    #[serde(skip)]
+    pub ws_channels: HashSet<u32>,
+    #[serde(skip)]
+    pub attachment_fetches: HashSet<(NodeId, String)>, // (owner, id) we've asked for and not heard back on
+    #[serde(skip)]
+    pub typing: HashMap<NodeId, SystemTime>, // node -> when their indicator expires
+    #[serde(skip)]
+    pub last_typing_sent: HashMap<ConversationId, SystemTime>,
 }
 
 //
 impl State {
     /// upon init and the host hasn't added any respects to their shrine yet
     pub fn new(node_id: NodeId) -> Self {
-        let stats = HashMap::from([(node_id.clone(), LeaderboardEntry { respects: 0 })]);
+        let stats = HashMap::from([(node_id.clone(), LeaderboardEntry::default())]);
         State {
             node_id, //your node
             discoverable: true, // perhaps this should be on by default
             contacts: Vec::new(), // your contacts. Use these to ask them about updates, if they have discoverable on
             stats, // HashMap<contact.node, their entry>, or what to use for rendering the frontend
+            leaderboard_seen: HashMap::new(),
+            offering_catalogue: default_catalogue(),
+            standings: HashMap::new(),
+            rank_changes: VecDeque::new(),
+            rank_notifications: VecDeque::new(),
+            hidden_entries: HashSet::new(),
+            deleted_entries: HashSet::new(),
+            respect_events: Vec::new(),
+            respect_rules: RespectRules::default(),
+            stats_updated_at: HashMap::new(),
+            gossip_entries: HashMap::new(),
+            gossip_shareable: HashMap::new(),
             pending_contact_requests: Vec::new(),
             incoming_contact_requests: Vec::new(),
             chat_history: Vec::new(), 
+            chat_unread: 0,
+            direct_conversations: HashMap::new(),
+            rooms: HashMap::new(),
+            room_invites: HashMap::new(),
+            congregations: HashMap::new(),
+            congregation_invites: HashMap::new(),
+            next_message_seq: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_micros() as u64,
+            seen_message_ids: VecDeque::new(),
+            history_synced_at: HashMap::new(),
+            rejected_remote_messages: BTreeMap::new(),
+            attachments: HashMap::new(),
+            mutes: HashSet::new(),
+            chat_filters: Vec::new(),
+            filter_matchers: FilterMatchers::default(),
+            chat_keys: HashMap::new(),
+            attachments_drive: None,
+            search_index: SearchIndex::default(),
+            ws_channels: HashSet::new(),
+            attachment_fetches: HashSet::new(),
+            typing: HashMap::new(),
+            last_typing_sent: HashMap::new(),
         }
     }
 
     pub fn fetch(our_node: NodeId) -> State {
         match get_state() {
             Some(state_bytes) => {
-                let desbytes = bincode::deserialize(&state_bytes).expect("Correctly deserialized state");
+                let mut desbytes: State = bincode::deserialize(&state_bytes).expect("Correctly deserialized state");
                 //desbytes.clients = HashSet::new();
+                desbytes.reindex_chat();
+                desbytes.rebuild_filter_matchers();
                 desbytes
             },
             None => State::new(our_node)
@@ -82,9 +463,26 @@ impl State {
         set_state(&state_bytes);
     }
 
-    pub fn add_respect(&mut self) {
-        let entry = self.stats.entry(self.node_id.clone()).or_insert_with(|| LeaderboardEntry { respects: 0});
+    /// returns the achievements this respect earned us
+    pub fn add_respect(&mut self, offering: &str) -> Result<Vec<AchievementKind>, RespectRefused> {
+        if !self.offering_catalogue.contains_key(offering) {
+            return Err(RespectRefused::UnknownOffering(offering.to_string()));
+        }
+        let now = SystemTime::now();
+        let credit_percent = self.check_respect_allowed(now)?;
+        let entry = self.stats.entry(self.node_id.clone()).or_default();
+        // respects from before offerings existed were all candles
+        if entry.offerings.is_empty() && entry.respects > 0 {
+            entry.offerings.insert(DEFAULT_OFFERING.to_string(), entry.respects);
+        }
         entry.respects += 1;
+        *entry.offerings.entry(offering.to_string()).or_default() += 1;
+        if credit_percent < 100 {
+            *entry.diminished.entry(offering.to_string()).or_default() += 100 - credit_percent;
+        }
+        self.rescore_our_entry();
+        self.record_respect_event(now);
+        Ok(self.record_devotion(now))
     }
 
     pub fn set_discoverable(&mut self, discoverable: bool) {
@@ -116,19 +514,555 @@ impl State {
     pub fn decline_contact(&mut self, other_node: NodeId) {
         if self.incoming_contact_requests.contains(&other_node) {
             self.incoming_contact_requests.retain(|node| node != &other_node);
+            self.forget_keys(&other_node);
         } else {
             println!("tried to decline a node that wasn't in your pending")
         }
     }
 
-    pub fn remove_entry(&mut self, node_id: &NodeId) {
-        self.stats.remove(node_id).is_some();
-        //println!("Removed entry for node_id {}: {}", node_id, removed);
+    pub fn remove_entry(&mut self, node_id: &NodeId, mode: RemoveMode) -> Result<(), String> {
+        if node_id == &self.node_id {
+            return Err("can't remove our own entry".to_string());
+        }
+        match mode {
+            RemoveMode::Hide => {
+                self.hidden_entries.insert(node_id.clone());
+            },
+            RemoveMode::Delete => {
+                self.hidden_entries.remove(node_id);
+                self.deleted_entries.insert(node_id.clone());
+                self.stats.remove(node_id);
+                self.stats_updated_at.remove(node_id);
+                self.gossip_entries.remove(node_id);
+                for seen in self.leaderboard_seen.values_mut() {
+                    seen.remove(node_id);
+                }
+            },
+            // a deleted contact's entry comes back with their next ContactUpdate
+            RemoveMode::Restore => {
+                let hidden = self.hidden_entries.remove(node_id);
+                let deleted = self.deleted_entries.remove(node_id);
+                if !hidden && !deleted {
+                    return Err(format!("{} wasn't hidden or deleted", node_id));
+                }
+            },
+        }
+        //println!("Removed entry for node_id {}: {:?}", node_id, mode);
+        Ok(())
+    }
+
+    /// whether `node` belongs on our boards at all
+    pub fn is_listed(&self, node: &NodeId) -> bool {
+        !self.hidden_entries.contains(node) && !self.deleted_entries.contains(node)
+    }
+
+    pub fn next_message_id(&mut self) -> String {
+        self.next_message_seq += 1;
+        format!("{}:{}", self.node_id, self.next_message_seq)
+    }
+
+    /// ids for our uploads and filters. shares the message counter so they stay unique across restarts too
+    pub fn next_local_id(&mut self) -> String {
+        self.next_message_seq += 1;
+        format!("{:x}", self.next_message_seq)
+    }
+
+    pub fn has_seen_message(&self, id: &str) -> bool {
+        self.seen_message_ids.iter().any(|seen| seen == id)
     }
 
     pub fn add_chat_message(&mut self, chat_message: ChatMessage) {
-        if self.chat_history.len() >= 50 {self.chat_history.remove(0);}
-        self.chat_history.push(chat_message);
+        let conversation = chat_message.conversation.clone();
+        if self.insert_chat_message(chat_message) {
+            if let Some((history, _)) = self.conversation_mut(&conversation) {
+                if history.len() > HISTORY_LEN {
+                    let evicted = history.remove(0);
+                    self.search_index.remove(&evicted.id);
+                }
+            }
+        }
+    }
+
+    /// files a message under its conversation without trimming it. false if there's nowhere to put it
+    fn insert_chat_message(&mut self, chat_message: ChatMessage) -> bool {
+        let counts_as_unread = chat_message.sender != self.node_id && !chat_message.is_hidden();
+        let (id, sender) = (chat_message.id.clone(), chat_message.sender.clone());
+        let conversation = chat_message.conversation.clone();
+        if !chat_message.deleted {
+            self.search_index.add(&chat_message.id, &chat_message.sender, &chat_message.content);
+        }
+        let Some((history, unread)) = self.conversation_mut(&conversation) else {
+            println!("no conversation {:?}, dropping message", conversation);
+            self.search_index.remove(&id);
+            return false;
+        };
+        history.push(chat_message);
+        if counts_as_unread {
+            *unread += 1;
+        }
+        if self.seen_message_ids.len() >= SEEN_MESSAGE_IDS {self.seen_message_ids.pop_front();}
+        self.seen_message_ids.push_back(id);
+        self.typing.remove(&sender);
+        true
+    }
+
+    /// a conversation's history and unread count. direct conversations are created on demand
+    fn conversation_mut(&mut self, conversation: &ConversationId) -> Option<(&mut Vec<ChatMessage>, &mut usize)> {
+        match conversation {
+            ConversationId::Shrine => Some((&mut self.chat_history, &mut self.chat_unread)),
+            ConversationId::Direct(node) => {
+                let conversation = self.direct_conversations.entry(node.clone()).or_default();
+                Some((&mut conversation.history, &mut conversation.unread))
+            },
+            ConversationId::Room(room_id) => self.rooms.get_mut(room_id).map(|room| (&mut room.history, &mut room.unread)),
+        }
+    }
+
+    /// who a message in this conversation goes out to
+    pub fn recipients(&self, conversation: &ConversationId) -> Vec<NodeId> {
+        match conversation {
+            ConversationId::Shrine => self.contacts.clone(),
+            ConversationId::Direct(node) if self.contacts.contains(node) => vec![node.clone()],
+            ConversationId::Direct(_) => Vec::new(),
+            ConversationId::Room(room_id) => match self.rooms.get(room_id) {
+                Some(room) if room.members.contains(&self.node_id) => room.members.iter()
+                    .filter(|member| **member != self.node_id)
+                    .cloned()
+                    .collect(),
+                _ => Vec::new(),
+            },
+        }
+    }
+
+    /// stores a message from a contact and marks it delivered to us. returns false if it was rejected,
+    /// i.e. a direct message from a non-contact or meant for someone else, or a room message from a
+    /// non-member, or one whose id doesn't belong to its sender. duplicates count as accepted
+    pub fn receive_chat_message(&mut self, mut chat_message: ChatMessage) -> bool {
+        if !chat_message.id.starts_with(&format!("{}:", chat_message.sender)) {
+            return false;
+        }
+        if let ConversationId::Direct(recipient) = &chat_message.conversation {
+            if recipient != &self.node_id || !self.contacts.contains(&chat_message.sender) {
+                return false;
+            }
+            chat_message.conversation = ConversationId::Direct(chat_message.sender.clone());
+        }
+        if let ConversationId::Room(room_id) = &chat_message.conversation {
+            match self.rooms.get(room_id) {
+                Some(room) if room.members.contains(&chat_message.sender) => {},
+                _ => return false,
+            }
+        }
+        if self.has_seen_message(&chat_message.id) {
+            return true;
+        }
+        chat_message.receipts = HashMap::from([(self.node_id.clone(), DeliveryStatus::Delivered)]);
+        chat_message.moderation = self.moderate(&chat_message.sender, &chat_message.content);
+        self.add_chat_message(chat_message);
+        true
+    }
+
+    pub fn all_chat_messages(&self) -> impl Iterator<Item = &ChatMessage> {
+        self.chat_history.iter()
+            .chain(self.direct_conversations.values().flat_map(|conversation| conversation.history.iter()))
+            .chain(self.rooms.values().flat_map(|room| room.history.iter()))
+    }
+
+    pub fn all_chat_messages_mut(&mut self) -> impl Iterator<Item = &mut ChatMessage> {
+        self.chat_history.iter_mut()
+            .chain(self.direct_conversations.values_mut().flat_map(|conversation| conversation.history.iter_mut()))
+            .chain(self.rooms.values_mut().flat_map(|room| room.history.iter_mut()))
+    }
+
+    pub fn find_chat_message_mut(&mut self, id: &str) -> Option<&mut ChatMessage> {
+        self.all_chat_messages_mut().find(|message| message.id == id)
+    }
+
+    /// the shrine room first, then rooms and direct conversations with the most recent activity on top
+    pub fn conversation_summaries(&self) -> Vec<ConversationSummary> {
+        let mut others: Vec<ConversationSummary> = self.direct_conversations.iter()
+            .map(|(node, conversation)| ConversationSummary {
+                conversation: ConversationId::Direct(node.clone()),
+                unread: conversation.unread,
+                unread_mentions: self.count_unread_mentions(&conversation.history),
+                last_message: conversation.history.iter().rev().find(|message| !message.is_hidden()).cloned(),
+            })
+            .chain(self.rooms.iter().map(|(room_id, room)| ConversationSummary {
+                conversation: ConversationId::Room(room_id.clone()),
+                unread: room.unread,
+                unread_mentions: self.count_unread_mentions(&room.history),
+                last_message: room.history.iter().rev().find(|message| !message.is_hidden()).cloned(),
+            }))
+            .collect();
+        others.sort_by_key(|summary| std::cmp::Reverse(summary.last_message.as_ref().map(|message| message.timestamp)));
+
+        let mut summaries = vec![ConversationSummary {
+            conversation: ConversationId::Shrine,
+            unread: self.chat_unread,
+            unread_mentions: self.count_unread_mentions(&self.chat_history),
+            last_message: self.chat_history.iter().rev().find(|message| !message.is_hidden()).cloned(),
+        }];
+        summaries.extend(others);
+        summaries
+    }
+
+    pub fn record_rejection(&mut self, error: ContentError) {
+        *self.rejected_remote_messages.entry(error.reason().to_string()).or_default() += 1;
+    }
+
+    /// notes a ContactUpdate from `contact`. true if it ends a silence long enough that we should backfill
+    pub fn heard_from_contact(&mut self, contact: &NodeId) -> bool {
+        let now = SystemTime::now();
+        let previous = self.stats_updated_at.insert(contact.clone(), now);
+        previous.is_some_and(|at| now.duration_since(at).map_or(false, |gap| gap >= CONTACT_SILENCE))
+    }
+
+    /// where a backfill from this contact should start
+    pub fn history_sync_point(&self, contact: &NodeId) -> SystemTime {
+        match self.history_synced_at.get(contact) {
+            Some(synced_at) => synced_at.checked_sub(HISTORY_SYNC_SLACK).unwrap_or(SystemTime::UNIX_EPOCH),
+            None => SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    /// everything a contact could have seen since `since`, in the same shape as live messages:
+    /// the shrine room, our direct conversation with them, and the rooms they're in
+    pub fn history_for(&self, requester: &NodeId, since: SystemTime) -> Vec<ChatMessage> {
+        let direct = self.direct_conversations.get(requester).map(|conversation| &conversation.history);
+        let rooms = self.rooms.values().filter(|room| room.members.contains(requester)).map(|room| &room.history);
+        self.chat_history.iter()
+            .chain(direct.into_iter().flatten())
+            .chain(rooms.flatten())
+            // first-party only: our other contacts never sent their messages to the requester
+            .filter(|message| message.sender == self.node_id || &message.sender == requester)
+            .filter(|message| message.timestamp >= since)
+            .map(|message| {
+                let mut outgoing = message.clone();
+                outgoing.receipts.clear();
+                outgoing.moderation = None;
+                // a direct message is addressed to whoever didn't send it
+                if let ConversationId::Direct(_) = outgoing.conversation {
+                    let recipient = if &outgoing.sender == requester { self.node_id.clone() } else { requester.clone() };
+                    outgoing.conversation = ConversationId::Direct(recipient);
+                }
+                outgoing
+            })
+            .collect()
+    }
+
+    /// merges a contact's backfill into our histories. only messages the responder wrote themselves are
+    /// taken, anything else would be on their word alone. messages we already hold only pick up later
+    /// edits and deletes, the ones we missed while offline or without a key.
+    /// touched histories are re-sorted by (timestamp, id). returns how many were added or brought up to date
+    pub fn merge_history(&mut self, responder: &NodeId, messages: Vec<ChatMessage>) -> usize {
+        let mut touched = HashSet::new();
+        let mut added = 0;
+        for mut chat_message in messages {
+            if &chat_message.sender != responder || !chat_message.id.starts_with(&format!("{}:", responder)) {
+                continue;
+            }
+            let reactions = std::mem::take(&mut chat_message.reactions);
+            if let Err(e) = sanitize_remote_message(&mut chat_message) {
+                self.record_rejection(e);
+                continue;
+            }
+            if self.has_seen_message(&chat_message.id) {
+                let updated = if chat_message.deleted {
+                    self.apply_delete(&chat_message.id, responder)
+                } else {
+                    match chat_message.edited_at {
+                        Some(edited_at) => self.apply_edit(&chat_message.id, responder, chat_message.content, edited_at)
+                            .map(|(conversation, _)| conversation),
+                        None => None,
+                    }
+                };
+                if updated.is_some() {
+                    added += 1;
+                }
+                continue;
+            }
+            let conversation = match &chat_message.conversation {
+                ConversationId::Shrine => ConversationId::Shrine,
+                ConversationId::Direct(recipient) => {
+                    if recipient != &self.node_id { continue; }
+                    ConversationId::Direct(responder.clone())
+                },
+                ConversationId::Room(room_id) => match self.rooms.get(room_id) {
+                    Some(room) if room.members.contains(responder) => {
+                        ConversationId::Room(room_id.clone())
+                    },
+                    _ => continue,
+                },
+            };
+            // the responder, us and whoever else is in the conversation
+            if !chat_message.deleted {
+                let mut members = self.recipients(&conversation);
+                members.extend([self.node_id.clone(), responder.clone()]);
+                chat_message.reactions = sanitize_reactions(reactions, &members);
+            }
+            chat_message.receipts = HashMap::from([(self.node_id.clone(), DeliveryStatus::Delivered)]);
+            chat_message.conversation = conversation.clone();
+            chat_message.moderation = self.moderate(&chat_message.sender, &chat_message.content);
+            if self.insert_chat_message(chat_message) {
+                touched.insert(conversation);
+                added += 1;
+            }
+        }
+        for conversation in touched {
+            if let Some((history, _)) = self.conversation_mut(&conversation) {
+                history.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.id.cmp(&b.id)));
+                let overflow = history.len().saturating_sub(HISTORY_LEN);
+                let evicted: Vec<ChatMessage> = history.drain(..overflow).collect();
+                for chat_message in evicted {
+                    self.search_index.remove(&chat_message.id);
+                }
+            }
+        }
+        added
+    }
+
+    fn count_unread_mentions(&self, history: &[ChatMessage]) -> usize {
+        history.iter().filter(|message| message.is_unread_mention_of(&self.node_id)).count()
+    }
+
+    /// one of our uploads, if it's on a message of ours that `node` was meant to see
+    pub fn attachment_for(&self, id: &str, node: &NodeId) -> Option<&Attachment> {
+        let attachment = self.attachments.get(id)?;
+        let shared = self.all_chat_messages().any(|message| {
+            message.sender == self.node_id
+                && message.attachments.iter().any(|attached| attached.id == id)
+                && self.recipients(&message.conversation).contains(node)
+        });
+        shared.then_some(attachment)
+    }
+
+    /// attachment metadata from any message we have, ours or someone else's
+    pub fn find_attachment(&self, owner: &NodeId, id: &str) -> Option<&Attachment> {
+        if owner == &self.node_id {
+            return self.attachments.get(id);
+        }
+        self.all_chat_messages()
+            .flat_map(|message| message.attachments.iter())
+            .find(|attachment| &attachment.owner == owner && attachment.id == id)
+    }
+
+    /// rooms are keyed by owner and name, so a node can't create two rooms with the same name
+    pub fn create_room(&mut self, name: String) -> Option<String> {
+        let room_id = format!("{}:{}", self.node_id, name);
+        if self.rooms.contains_key(&room_id) {
+            return None;
+        }
+        self.rooms.insert(room_id.clone(), Room {
+            name,
+            owner: self.node_id.clone(),
+            members: vec![self.node_id.clone()],
+            invited: Vec::new(),
+            history: Vec::new(),
+            unread: 0,
+        });
+        Some(room_id)
+    }
+
+    /// drops a room along with its messages' search entries
+    pub fn remove_room(&mut self, room_id: &str) -> Option<Room> {
+        let room = self.rooms.remove(room_id)?;
+        for chat_message in &room.history {
+            self.search_index.remove(&chat_message.id);
+        }
+        Some(room)
+    }
+
+    /// the room, if we own it
+    pub fn owned_room_mut(&mut self, room_id: &str) -> Option<&mut Room> {
+        self.rooms.get_mut(room_id).filter(|room| room.owner == self.node_id)
+    }
+
+    pub fn is_room_member(&self, node: &NodeId) -> bool {
+        self.rooms.values().any(|room| room.members.contains(node) || room.invited.contains(node))
+    }
+
+    /// turns a pending invite into a local room, returning its owner
+    pub fn accept_room_invite(&mut self, room_id: &str) -> Option<NodeId> {
+        let invite = self.room_invites.remove(room_id)?;
+        let mut members = invite.members;
+        if !members.contains(&self.node_id) {
+            members.push(self.node_id.clone());
+        }
+        self.rooms.insert(room_id.to_string(), Room {
+            name: invite.name,
+            owner: invite.owner.clone(),
+            members,
+            invited: Vec::new(),
+            history: Vec::new(),
+            unread: 0,
+        });
+        Some(invite.owner)
+    }
+
+    /// replaces the content of a message if `editor` sent it, keeping the old content in its history.
+    /// stale or repeated edits are ignored. returns the message's conversation if it was applied,
+    /// and whether the edit newly mentions us in a way that gets to ping us
+    pub fn apply_edit(&mut self, id: &str, editor: &NodeId, content: String, edited_at: SystemTime) -> Option<(ConversationId, bool)> {
+        let node_id = self.node_id.clone();
+        let chat_message = self.find_chat_message_mut(id)?;
+        if &chat_message.sender != editor || chat_message.deleted {
+            return None;
+        }
+        if edited_at <= chat_message.edited_at.unwrap_or(chat_message.timestamp) {
+            return None;
+        }
+        let mentioned_before = chat_message.mentions.contains(&node_id);
+        chat_message.mentions = parse_mentions(&content);
+        let mentioned_now = chat_message.mentions.contains(&node_id);
+        let previous = std::mem::replace(&mut chat_message.content, content);
+        chat_message.edit_history.push(previous);
+        chat_message.edited_at = Some(edited_at);
+        let conversation = chat_message.conversation.clone();
+        let (sender, content) = (chat_message.sender.clone(), chat_message.content.clone());
+        self.search_index.add(id, &sender, &content);
+        // an edit can't be used to slip past a filter
+        let moderation = self.moderate(&sender, &content);
+        let mentions_us = !mentioned_before && mentioned_now && moderation.is_none();
+        if let Some(chat_message) = self.find_chat_message_mut(id) {
+            chat_message.moderation = moderation;
+        }
+        Some((conversation, mentions_us))
+    }
+
+    /// turns a message into a tombstone if `editor` sent it. returns its conversation if it was applied
+    pub fn apply_delete(&mut self, id: &str, editor: &NodeId) -> Option<ConversationId> {
+        let chat_message = self.find_chat_message_mut(id)?;
+        if &chat_message.sender != editor || chat_message.deleted {
+            return None;
+        }
+        chat_message.deleted = true;
+        chat_message.content.clear();
+        chat_message.edit_history.clear();
+        chat_message.reactions.clear();
+        chat_message.mentions.clear();
+        chat_message.attachments.clear();
+        let conversation = chat_message.conversation.clone();
+        self.search_index.remove(id);
+        Some(conversation)
+    }
+
+    /// whether `node` wrote the message or is in its conversation, the only nodes that may react to it
+    pub fn shares_message(&self, id: &str, node: &NodeId) -> bool {
+        self.all_chat_messages()
+            .find(|message| message.id == id)
+            .is_some_and(|message| &message.sender == node || self.recipients(&message.conversation).contains(node))
+    }
+
+    /// adds or removes one node's reaction, at most one per node and emoji.
+    /// returns the message's conversation if anything changed
+    pub fn apply_reaction(&mut self, id: &str, reactor: &NodeId, emoji: &str, add: bool) -> Option<ConversationId> {
+        if emoji.is_empty() || emoji.len() > MAX_REACTION_LEN || emoji.chars().any(char::is_whitespace) {
+            return None;
+        }
+        let chat_message = self.find_chat_message_mut(id)?;
+        if chat_message.deleted {
+            return None;
+        }
+        let reactors = chat_message.reactions.entry(emoji.to_string()).or_default();
+        let changed = if add {
+            if reactors.contains(reactor) {
+                false
+            } else {
+                reactors.push(reactor.clone());
+                true
+            }
+        } else {
+            let before = reactors.len();
+            reactors.retain(|node| node != reactor);
+            reactors.len() != before
+        };
+        if reactors.is_empty() {
+            chat_message.reactions.remove(emoji);
+        }
+        changed.then(|| chat_message.conversation.clone())
+    }
+
+    /// moves a recipient's receipt on one of our messages forward. returns true if it changed
+    pub fn update_receipt(&mut self, id: &str, recipient: &NodeId, status: DeliveryStatus) -> bool {
+        let node_id = self.node_id.clone();
+        let Some(chat_message) = self.find_chat_message_mut(id).filter(|message| message.sender == node_id) else { return false };
+        // only nodes we sent it to, or anyone could list themselves as having read it
+        match chat_message.receipts.get_mut(recipient) {
+            // a rejection only answers a message that hasn't been acked yet
+            Some(current) if *current < status && (status != DeliveryStatus::Rejected || *current <= DeliveryStatus::Sent) => {
+                *current = status;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// a send to this recipient failed, so it gets retried on the next timer tick
+    pub fn requeue_chat_message(&mut self, id: &str, recipient: &NodeId) {
+        let node_id = self.node_id.clone();
+        if let Some(chat_message) = self.find_chat_message_mut(id).filter(|message| message.sender == node_id) {
+            if chat_message.receipts.get(recipient) == Some(&DeliveryStatus::Sent) {
+                chat_message.receipts.insert(recipient.clone(), DeliveryStatus::Queued);
+            }
+        }
+    }
+
+    /// clears a conversation's unread count and marks its delivered messages as read,
+    /// returning (sender, id) of the ones that changed
+    pub fn mark_chat_read(&mut self, conversation: &ConversationId) -> Vec<(NodeId, String)> {
+        let (history, unread) = match conversation {
+            ConversationId::Shrine => (&mut self.chat_history, &mut self.chat_unread),
+            ConversationId::Direct(node) => match self.direct_conversations.get_mut(node) {
+                Some(conversation) => (&mut conversation.history, &mut conversation.unread),
+                None => return Vec::new(),
+            },
+            ConversationId::Room(room_id) => match self.rooms.get_mut(room_id) {
+                Some(room) => (&mut room.history, &mut room.unread),
+                None => return Vec::new(),
+            },
+        };
+        *unread = 0;
+        let mut newly_read = Vec::new();
+        for chat_message in history.iter_mut() {
+            if let Some(status) = chat_message.receipts.get_mut(&self.node_id) {
+                if *status == DeliveryStatus::Delivered {
+                    *status = DeliveryStatus::Read;
+                    newly_read.push((chat_message.sender.clone(), chat_message.id.clone()));
+                }
+            }
+        }
+        newly_read
+    }
+
+    /// true if enough time has passed since we last told this conversation we're typing
+    pub fn try_mark_typing(&mut self, conversation: &ConversationId) -> bool {
+        let now = SystemTime::now();
+        if let Some(last) = self.last_typing_sent.get(conversation) {
+            if now.duration_since(*last).unwrap_or_default() < TYPING_MIN_INTERVAL {
+                return false;
+            }
+        }
+        self.last_typing_sent.insert(conversation.clone(), now);
+        true
+    }
+
+    /// refreshes a contact's typing indicator, returns false if they're sending them too often
+    pub fn record_typing(&mut self, node: NodeId) -> bool {
+        let now = SystemTime::now();
+        if let Some(expires_at) = self.typing.get(&node) {
+            let last_seen = *expires_at - TYPING_TTL;
+            if now.duration_since(last_seen).unwrap_or_default() < TYPING_MIN_INTERVAL {
+                return false;
+            }
+        }
+        self.typing.insert(node, now + TYPING_TTL);
+        true
+    }
+
+    pub fn prune_typing(&mut self) {
+        let now = SystemTime::now();
+        self.typing.retain(|_, expires_at| *expires_at > now);
+        self.last_typing_sent.retain(|_, sent_at| now.duration_since(*sent_at).unwrap_or_default() < TYPING_MIN_INTERVAL);
     }
 }
 
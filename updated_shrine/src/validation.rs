@@ -0,0 +1,234 @@
+use unicode_normalization::UnicodeNormalization;
+
+use kinode_process_lib::NodeId;
+use std::collections::BTreeMap;
+
+use crate::attachments::{is_valid_remote, MAX_ATTACHMENTS_PER_MESSAGE};
+use crate::structs::{ChatMessage, MAX_REACTION_LEN};
+
+pub const MAX_MESSAGE_CHARS: usize = 2000;
+/// anything bigger than this can't be under MAX_MESSAGE_CHARS, so don't bother normalizing it
+const MAX_MESSAGE_BYTES: usize = MAX_MESSAGE_CHARS * 4;
+/// older versions beyond this are dropped from remote messages
+pub const MAX_EDIT_HISTORY: usize = 20;
+pub const MAX_MENTIONS: usize = 20;
+/// distinct emoji kept on a backfilled message
+pub const MAX_REMOTE_REACTIONS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentError {
+    Empty,
+    TooLong,
+}
+
+impl ContentError {
+    /// key for the rejection counters
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ContentError::Empty => "empty",
+            ContentError::TooLong => "too_long",
+        }
+    }
+}
+
+impl std::fmt::Display for ContentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContentError::Empty => write!(f, "message is empty"),
+            ContentError::TooLong => write!(f, "message is longer than {} characters", MAX_MESSAGE_CHARS),
+        }
+    }
+}
+
+/// NFC-normalizes, strips control and bidi override characters (newlines and tabs survive) and trims.
+/// the same pipeline runs on what we send and on what we receive
+pub fn sanitize_content(raw: &str) -> Result<String, ContentError> {
+    if raw.len() > MAX_MESSAGE_BYTES {
+        return Err(ContentError::TooLong);
+    }
+    let sanitized: String = raw.nfc()
+        .filter(|c| !is_stripped(*c))
+        .collect();
+    let sanitized = sanitized.trim();
+    if sanitized.is_empty() {
+        return Err(ContentError::Empty);
+    }
+    if sanitized.chars().count() > MAX_MESSAGE_CHARS {
+        return Err(ContentError::TooLong);
+    }
+    Ok(sanitized.to_string())
+}
+
+/// sanitizes everything user-supplied on a message from another node. tombstones are emptied
+/// rather than rejected, and bad edit history or attachments are dropped rather than failing the message.
+/// reactions are always dropped: each one arrives on its own, from the node that reacted
+pub fn sanitize_remote_message(chat_message: &mut ChatMessage) -> Result<(), ContentError> {
+    if chat_message.deleted {
+        chat_message.content.clear();
+        chat_message.edit_history.clear();
+        chat_message.reactions.clear();
+        chat_message.mentions.clear();
+        chat_message.attachments.clear();
+        return Ok(());
+    }
+    let sender = chat_message.sender.clone();
+    chat_message.attachments.retain(|attachment| is_valid_remote(attachment, &sender));
+    chat_message.attachments.truncate(MAX_ATTACHMENTS_PER_MESSAGE);
+    // an attachment can go out without any text
+    chat_message.content = match sanitize_content(&chat_message.content) {
+        Ok(content) => content,
+        Err(ContentError::Empty) if !chat_message.attachments.is_empty() => String::new(),
+        Err(e) => return Err(e),
+    };
+    // mentions are always derived from the content, never taken on the sender's word
+    chat_message.mentions = parse_mentions(&chat_message.content);
+    let edit_history = std::mem::take(&mut chat_message.edit_history);
+    let skip = edit_history.len().saturating_sub(MAX_EDIT_HISTORY);
+    chat_message.edit_history = edit_history.into_iter()
+        .skip(skip)
+        .filter_map(|content| sanitize_content(&content).ok())
+        .collect();
+    chat_message.reactions.clear();
+    Ok(())
+}
+
+/// reactions on a backfilled message, which we may have missed one by one. only well-formed emoji,
+/// each reactor once and only nodes in the message's conversation
+pub fn sanitize_reactions(reactions: BTreeMap<String, Vec<NodeId>>, members: &[NodeId]) -> BTreeMap<String, Vec<NodeId>> {
+    reactions.into_iter()
+        .filter(|(emoji, _)| !emoji.is_empty() && emoji.len() <= MAX_REACTION_LEN && !emoji.chars().any(char::is_whitespace))
+        .filter_map(|(emoji, reactors)| {
+            let mut kept: Vec<NodeId> = Vec::new();
+            for reactor in reactors {
+                if members.contains(&reactor) && !kept.contains(&reactor) {
+                    kept.push(reactor);
+                }
+            }
+            (!kept.is_empty()).then_some((emoji, kept))
+        })
+        .take(MAX_REMOTE_REACTIONS)
+        .collect()
+}
+
+/// `@node.os` style mentions, lowercased and deduplicated. an `@` in the middle of a word
+/// (like an email address) doesn't count, and trailing dots are punctuation, not part of the node
+pub fn parse_mentions(content: &str) -> Vec<NodeId> {
+    let mut mentions: Vec<NodeId> = Vec::new();
+    let mut previous: Option<char> = None;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        let at_word_start = previous.map_or(true, |p| !p.is_alphanumeric());
+        previous = Some(c);
+        if c != '@' || !at_word_start {
+            continue;
+        }
+        let mut node = String::new();
+        while let Some(&next) = chars.peek() {
+            if !(next.is_ascii_alphanumeric() || matches!(next, '.' | '-' | '_')) {
+                break;
+            }
+            node.push(next.to_ascii_lowercase());
+            previous = Some(next);
+            chars.next();
+        }
+        let node = node.trim_end_matches('.');
+        if node.contains('.') && !node.starts_with('.') && !mentions.iter().any(|mention| mention == node) {
+            mentions.push(node.to_string());
+            if mentions.len() >= MAX_MENTIONS {
+                break;
+            }
+        }
+    }
+    mentions
+}
+
+fn is_stripped(c: char) -> bool {
+    let bidi_override = matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}');
+    (c.is_control() && c != '\n' && c != '\t') || bidi_override
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::ConversationId;
+
+    fn remote(content: &str) -> ChatMessage {
+        ChatMessage::new("bob.os:1".to_string(), "bob.os".to_string(), content.to_string(), ConversationId::Shrine)
+    }
+
+    #[test]
+    fn sanitize_strips_controls_and_bidi_overrides() {
+        assert_eq!(sanitize_content("  hi\u{0007} there\u{202E}\n\tok  ").unwrap(), "hi there\n\tok");
+    }
+
+    #[test]
+    fn sanitize_normalizes_to_nfc() {
+        assert_eq!(sanitize_content("cafe\u{0301}").unwrap(), "caf\u{00E9}");
+    }
+
+    #[test]
+    fn sanitize_rejects_empty_and_too_long() {
+        assert_eq!(sanitize_content(" \u{0000}\u{202A} "), Err(ContentError::Empty));
+        assert_eq!(sanitize_content(&"a".repeat(MAX_MESSAGE_CHARS)).unwrap().chars().count(), MAX_MESSAGE_CHARS);
+        assert_eq!(sanitize_content(&"a".repeat(MAX_MESSAGE_CHARS + 1)), Err(ContentError::TooLong));
+        // counted in characters, not bytes
+        assert!(sanitize_content(&"é".repeat(MAX_MESSAGE_CHARS)).is_ok());
+    }
+
+    #[test]
+    fn remote_messages_lose_what_the_sender_claimed() {
+        let mut chat_message = remote("hello @carol.os");
+        chat_message.mentions = vec!["dave.os".to_string()];
+        chat_message.reactions.insert("👍".to_string(), vec!["dave.os".to_string()]);
+        chat_message.edit_history = (0..MAX_EDIT_HISTORY + 5).map(|i| format!("v{}", i)).chain([" ".to_string()]).collect();
+        sanitize_remote_message(&mut chat_message).unwrap();
+        assert_eq!(chat_message.mentions, vec!["carol.os".to_string()]);
+        assert!(chat_message.reactions.is_empty());
+        assert_eq!(chat_message.edit_history.len(), MAX_EDIT_HISTORY - 1);
+        assert_eq!(chat_message.edit_history.last().map(String::as_str), Some("v24"));
+    }
+
+    #[test]
+    fn remote_tombstones_are_emptied() {
+        let mut chat_message = remote("");
+        chat_message.deleted = true;
+        chat_message.edit_history = vec!["old".to_string()];
+        assert!(sanitize_remote_message(&mut chat_message).is_ok());
+        assert!(chat_message.content.is_empty() && chat_message.edit_history.is_empty());
+        assert_eq!(sanitize_remote_message(&mut remote("   ")), Err(ContentError::Empty));
+    }
+
+    #[test]
+    fn backfilled_reactions_keep_members_once() {
+        let members = vec!["alice.os".to_string(), "bob.os".to_string()];
+        let reactions = BTreeMap::from([
+            ("👍".to_string(), vec!["bob.os".to_string(), "bob.os".to_string(), "mallory.os".to_string(), "alice.os".to_string()]),
+            ("🔥".to_string(), vec!["mallory.os".to_string()]),
+            ("no way".to_string(), vec!["alice.os".to_string()]),
+        ]);
+        let kept = sanitize_reactions(reactions, &members);
+        assert_eq!(kept, BTreeMap::from([("👍".to_string(), vec!["bob.os".to_string(), "alice.os".to_string()])]));
+
+        let many = (0..MAX_REMOTE_REACTIONS + 5).map(|i| (format!("e{}", i), vec!["alice.os".to_string()])).collect();
+        assert_eq!(sanitize_reactions(many, &members).len(), MAX_REMOTE_REACTIONS);
+    }
+
+    #[test]
+    fn mentions_are_lowercased_and_deduplicated() {
+        assert_eq!(parse_mentions("@Alice.os and @alice.os, then @bob.os."), vec!["alice.os".to_string(), "bob.os".to_string()]);
+    }
+
+    #[test]
+    fn mentions_need_a_word_start_and_a_dot() {
+        assert!(parse_mentions("mail me at me@alice.os").is_empty());
+        assert!(parse_mentions("@alice @.os @").is_empty());
+        assert_eq!(parse_mentions("(@alice.os)"), vec!["alice.os".to_string()]);
+        assert_eq!(parse_mentions("@my-node_1.os..."), vec!["my-node_1.os".to_string()]);
+    }
+
+    #[test]
+    fn mentions_are_capped() {
+        let content: Vec<String> = (0..MAX_MENTIONS + 5).map(|i| format!("@node{}.os", i)).collect();
+        assert_eq!(parse_mentions(&content.join(" ")).len(), MAX_MENTIONS);
+    }
+}
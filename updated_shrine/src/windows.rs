@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+use crate::structs::{LeaderboardEntry, State};
+
+pub const SECS_PER_DAY: u64 = 86_400;
+/// longer than any month, so pruning can't drop an event the monthly count still needs
+const RESPECT_EVENT_RETENTION: Duration = Duration::from_secs(32 * SECS_PER_DAY);
+
+/// leaderboard windows, all in utc. weeks start on monday
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Window {
+    Daily,
+    Weekly,
+    Monthly,
+    #[default]
+    AllTime,
+}
+
+impl Window {
+    pub fn from_param(value: &str) -> Result<Self, String> {
+        match value {
+            "daily" => Ok(Window::Daily),
+            "weekly" => Ok(Window::Weekly),
+            "monthly" => Ok(Window::Monthly),
+            "all_time" => Ok(Window::AllTime),
+            other => Err(format!("bad window {other:?}")),
+        }
+    }
+
+    /// which period of this window `at` falls in. only comparable within the same window
+    pub fn period(self, at: SystemTime) -> u64 {
+        let day = at.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs() / SECS_PER_DAY;
+        match self {
+            Window::Daily => day,
+            // 1970-01-01 was a thursday
+            Window::Weekly => (day + 3) / 7,
+            Window::Monthly => {
+                let (year, month) = year_month(day);
+                year * 12 + (month - 1)
+            },
+            Window::AllTime => 0,
+        }
+    }
+}
+
+/// a node's respects in the periods that were current when it last updated. the period ids let
+/// a receiver tell that a count has rolled over even if the node hasn't been heard from since
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowCounts {
+    pub day: u64,
+    pub daily: u64,
+    pub week: u64,
+    pub weekly: u64,
+    pub month: u64,
+    pub monthly: u64,
+}
+
+impl WindowCounts {
+    pub fn from_events(events: &[SystemTime], now: SystemTime) -> Self {
+        let count = |window: Window| {
+            let current = window.period(now);
+            events.iter().filter(|at| window.period(**at) == current).count() as u64
+        };
+        WindowCounts {
+            day: Window::Daily.period(now),
+            daily: count(Window::Daily),
+            week: Window::Weekly.period(now),
+            weekly: count(Window::Weekly),
+            month: Window::Monthly.period(now),
+            monthly: count(Window::Monthly),
+        }
+    }
+}
+
+impl LeaderboardEntry {
+    /// respects in the period of `window` that's current at `now`. a count from a period that has
+    /// since ended reads as 0
+    pub fn respects_in(&self, window: Window, now: SystemTime) -> u64 {
+        let (period, count) = match window {
+            Window::Daily => (self.windows.day, self.windows.daily),
+            Window::Weekly => (self.windows.week, self.windows.weekly),
+            Window::Monthly => (self.windows.month, self.windows.monthly),
+            Window::AllTime => return self.respects,
+        };
+        if period == window.period(now) { count } else { 0 }
+    }
+}
+
+impl State {
+    pub fn record_respect_event(&mut self, at: SystemTime) {
+        self.respect_events.push(at);
+        self.refresh_windows();
+    }
+
+    /// recounts our own windows, so what we send rolls over at the boundaries even without new respects
+    pub fn refresh_windows(&mut self) {
+        let now = SystemTime::now();
+        self.respect_events.retain(|at| now.duration_since(*at).map_or(true, |age| age < RESPECT_EVENT_RETENTION));
+        let windows = WindowCounts::from_events(&self.respect_events, now);
+        self.stats.entry(self.node_id.clone()).or_default().windows = windows;
+    }
+}
+
+/// (year, month 1-12) of a day counted from 1970-01-01, after Howard Hinnant's civil_from_days
+fn year_month(day: u64) -> (u64, u64) {
+    let z = day + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // seconds into a utc day counted from 1970-01-01
+    fn at(day: u64, secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(day * SECS_PER_DAY + secs)
+    }
+
+    #[test]
+    fn civil_months() {
+        assert_eq!(year_month(0), (1970, 1));
+        assert_eq!(year_month(11_016), (2000, 2)); // 2000-02-29
+        assert_eq!(year_month(19_782), (2024, 2)); // 2024-02-29
+        assert_eq!(year_month(19_783), (2024, 3));
+        assert_eq!(year_month(47_541), (2100, 3)); // no 2100-02-29
+    }
+
+    #[test]
+    fn months_roll_over_at_midnight_and_new_year() {
+        let monthly = |day, secs| Window::Monthly.period(at(day, secs));
+        assert_eq!(monthly(19_782, SECS_PER_DAY - 1) + 1, monthly(19_783, 0));
+        // 2023-12-31 to 2024-01-01
+        assert_eq!(monthly(19_722, SECS_PER_DAY - 1) + 1, monthly(19_723, 0));
+        assert_eq!(monthly(19_723, 0), 2024 * 12);
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        let weekly = |day| Window::Weekly.period(at(day, 0));
+        // 1970-01-04 was a sunday, 1970-01-05 a monday
+        assert_eq!(weekly(0), weekly(3));
+        assert_eq!(weekly(3) + 1, weekly(4));
+        // a week spanning the new year stays one week: monday 2024-12-30 to sunday 2025-01-05
+        assert_eq!(weekly(20_087), weekly(20_093));
+        assert_eq!(weekly(20_093) + 1, weekly(20_094));
+    }
+
+    #[test]
+    fn counts_only_the_current_period() {
+        let now = at(20_094, 60); // monday 2025-01-06
+        let events = [at(20_093, 0), at(20_094, 0), at(20_094, 30), at(20_070, 0)];
+        let counts = WindowCounts::from_events(&events, now);
+        assert_eq!((counts.daily, counts.weekly, counts.monthly), (2, 2, 3));
+        assert_eq!(counts.week, Window::Weekly.period(now));
+    }
+
+    #[test]
+    fn stale_counts_read_as_zero() {
+        let then = at(20_093, 0);
+        let entry = LeaderboardEntry { respects: 9, windows: WindowCounts::from_events(&[then], then), ..LeaderboardEntry::default() };
+        assert_eq!(entry.respects_in(Window::Daily, then), 1);
+        assert_eq!(entry.respects_in(Window::Daily, at(20_094, 0)), 0);
+        assert_eq!(entry.respects_in(Window::Weekly, at(20_094, 0)), 0);
+        assert_eq!(entry.respects_in(Window::Monthly, at(20_094, 0)), 1);
+        assert_eq!(entry.respects_in(Window::AllTime, at(30_000, 0)), 9);
+    }
+}